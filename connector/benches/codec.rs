@@ -0,0 +1,74 @@
+use bytes::BytesMut;
+use connector::{CodecError, Compression, CompressionPolicy, LegacyMessageCodec, Message, MessageCodec, Protocol};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use serde_json::json;
+use tokio_util::codec::{Decoder, Encoder};
+
+const COMPRESSIONS: [(&str, Compression); 5] = [
+    ("none", Compression::None),
+    ("zstd", Compression::Zstd(3)),
+    ("lz4", Compression::Lz4),
+    ("gzip", Compression::Gzip(6)),
+    ("snappy", Compression::Snappy),
+];
+
+fn sample_message(compression: Compression) -> Message {
+    Message::new(
+        compression,
+        Protocol::Json(json!({
+            "measurement": "http_requests",
+            "bucket": "test",
+            "organization": "arslanelabs",
+            "fields": {
+                "http_method": "POST",
+                "http_protocol": "https",
+                "http_host": "echo.free.beeceptor.com",
+                "http_path": "/",
+                "client_ip": "127.0.0.1",
+                "name": "John Doe",
+                "age": 30
+            },
+            "tags": {
+                "content_type": "application/json"
+            }
+        })),
+    )
+}
+
+fn round_trip<C>(codec: &mut C, message: Message) -> Message
+where
+    C: Encoder<Message, Error = CodecError> + Decoder<Item = Message, Error = CodecError>,
+{
+    let mut buf = BytesMut::new();
+    codec.encode(message, &mut buf).unwrap();
+    codec.decode(&mut buf).unwrap().unwrap()
+}
+
+fn bench_codecs(c: &mut Criterion) {
+    let mut group = c.benchmark_group("codec_round_trip");
+
+    for (name, compression) in COMPRESSIONS.clone() {
+        let message = sample_message(compression);
+
+        group.bench_with_input(BenchmarkId::new("legacy", name), &message, |b, message| {
+            b.iter(|| round_trip(&mut LegacyMessageCodec, message.clone()))
+        });
+        group.bench_with_input(BenchmarkId::new("framed", name), &message, |b, message| {
+            let mut codec = MessageCodec::new().with_compression_policy(CompressionPolicy::always());
+            b.iter(|| round_trip(&mut codec, message.clone()))
+        });
+    }
+
+    group.finish();
+
+    for (name, compression) in COMPRESSIONS.clone() {
+        let mut legacy = BytesMut::new();
+        LegacyMessageCodec.encode(sample_message(compression.clone()), &mut legacy).unwrap();
+        let mut framed = BytesMut::new();
+        MessageCodec::new().with_compression_policy(CompressionPolicy::always()).encode(sample_message(compression), &mut framed).unwrap();
+        println!("{}: legacy frame {} bytes, framed {} bytes", name, legacy.len(), framed.len());
+    }
+}
+
+criterion_group!(benches, bench_codecs);
+criterion_main!(benches);
@@ -1,4 +1,5 @@
 use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
 
 use crate::Message;
 use crate::http::{HttpClient, HttpClientError};
@@ -14,6 +15,49 @@ pub enum DataConnectorError {
     InfluxDbClientError(#[from] InfluxDbClientError),
     #[error("Other error: {0}")]
     OtherError(String),
+    /// Failure of a batch, shared by each of its messages.
+    #[error("Batch error: {message}")]
+    BatchError { kind: ErrorKind, message: String },
+}
+
+/// Broad cause of a `DataConnectorError`, for deciding whether a write is
+/// worth retrying.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    Timeout,
+    /// The request could not be sent or its response not received.
+    Request,
+    /// The server answered with an error status.
+    Http,
+    Serialization,
+    Other,
+}
+
+impl DataConnectorError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            DataConnectorError::HttpClientError(e) => http_error_kind(e),
+            DataConnectorError::InfluxDbClientError(InfluxDbClientError::HttpClientError(e)) => http_error_kind(e),
+            DataConnectorError::InfluxDbClientError(InfluxDbClientError::SerializationError(_)) => ErrorKind::Serialization,
+            DataConnectorError::InfluxDbClientError(_) => ErrorKind::Other,
+            DataConnectorError::OtherError(_) => ErrorKind::Other,
+            DataConnectorError::BatchError { kind, .. } => *kind,
+        }
+    }
+}
+
+fn http_error_kind(error: &HttpClientError) -> ErrorKind {
+    match error {
+        HttpClientError::RequestError(e) if e.is_timeout() => ErrorKind::Timeout,
+        HttpClientError::RequestError(_) => ErrorKind::Request,
+        HttpClientError::TimeoutError(_) => ErrorKind::Timeout,
+        HttpClientError::DeserializeError(_) => ErrorKind::Serialization,
+        HttpClientError::InvalidMethodError(_) => ErrorKind::Other,
+        HttpClientError::HttpError(_) => ErrorKind::Http,
+        HttpClientError::AuthError(_) => ErrorKind::Http,
+        HttpClientError::UnsupportedContentType(_) | HttpClientError::EncodeError(_) => ErrorKind::Serialization,
+    }
 }
 
 #[async_trait]
@@ -21,6 +65,24 @@ pub trait DataConnector: Send + Sync
 {
     async fn write(&self, data: Message) -> Result<Message, DataConnectorError>;
     async fn read(&self, data: Message) -> Result<Message, DataConnectorError>;
+
+    /// Reads everything the query yields, which may take several messages,
+    /// such as the pages of a paged API. By default that is what `read`
+    /// returns.
+    async fn read_all(&self, data: Message) -> Result<Vec<Message>, DataConnectorError> {
+        Ok(vec![self.read(data).await?])
+    }
+
+    /// Writes several messages, returning one result per message in the
+    /// same order. Connectors able to send them in one request override
+    /// this; by default they are written one after the other.
+    async fn write_batch(&self, data: Vec<Message>) -> Vec<Result<Message, DataConnectorError>> {
+        let mut results = Vec::with_capacity(data.len());
+        for message in data {
+            results.push(self.write(message).await);
+        }
+        results
+    }
 }
 
 pub enum Connector {
@@ -42,4 +104,18 @@ impl Connector {
             Connector::InfluxDb(client) => client.read(data).await,
         }
     }
+
+    pub async fn read_all(&self, data: Message) -> Result<Vec<Message>, DataConnectorError> {
+        match self {
+            Connector::Http(client) => client.read_all(data).await,
+            Connector::InfluxDb(client) => client.read_all(data).await,
+        }
+    }
+
+    pub async fn write_batch(&self, data: Vec<Message>) -> Vec<Result<Message, DataConnectorError>> {
+        match self {
+            Connector::Http(client) => client.write_batch(data).await,
+            Connector::InfluxDb(client) => client.write_batch(data).await,
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,133 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+use reqwest::Client;
+use serde::Deserialize;
+use tokio::sync::Mutex;
+use super::HttpClientError;
+
+/// Tokens are renewed this long before they expire, so none runs out in
+/// flight.
+const EXPIRY_MARGIN: Duration = Duration::from_secs(30);
+
+/// How an `HttpClient` authenticates its requests.
+#[derive(Clone)]
+pub enum HttpAuth {
+    Basic { username: String, password: Option<String> },
+    Bearer(String),
+    /// Tokens fetched through the OAuth2 client credentials grant.
+    OAuth2(OAuth2ClientCredentials),
+}
+
+impl fmt::Debug for HttpAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HttpAuth::Basic { username, .. } => f.debug_struct("Basic").field("username", username).finish_non_exhaustive(),
+            HttpAuth::Bearer(_) => f.write_str("Bearer"),
+            HttpAuth::OAuth2(credentials) => f.debug_tuple("OAuth2").field(credentials).finish(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct OAuth2ClientCredentials {
+    pub token_url: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub scopes: Vec<String>,
+}
+
+impl fmt::Debug for OAuth2ClientCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OAuth2ClientCredentials")
+            .field("token_url", &self.token_url)
+            .field("client_id", &self.client_id)
+            .field("scopes", &self.scopes)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    /// Seconds the token is valid for; without it the token is used until
+    /// rejected.
+    expires_in: Option<u64>,
+}
+
+struct AccessToken {
+    value: String,
+    expires_at: Option<Instant>,
+}
+
+impl AccessToken {
+    fn is_fresh(&self) -> bool {
+        self.expires_at.is_none_or(|expires_at| Instant::now() + EXPIRY_MARGIN < expires_at)
+    }
+}
+
+impl fmt::Debug for Authenticator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.auth.fmt(f)
+    }
+}
+
+/// Produces the `Authorization` header for an `HttpAuth`, caching OAuth2
+/// tokens until they expire.
+pub(crate) struct Authenticator {
+    auth: HttpAuth,
+    token: Mutex<Option<AccessToken>>,
+}
+
+impl Authenticator {
+    pub(crate) fn new(auth: HttpAuth) -> Self {
+        Authenticator { auth, token: Mutex::new(None) }
+    }
+
+    /// Whether a 401 response is worth retrying with a fresh token.
+    pub(crate) fn renews_tokens(&self) -> bool {
+        matches!(self.auth, HttpAuth::OAuth2(_))
+    }
+
+    pub(crate) async fn authorize(&self, client: &Client, request: reqwest::RequestBuilder) -> Result<reqwest::RequestBuilder, HttpClientError> {
+        Ok(match &self.auth {
+            HttpAuth::Basic { username, password } => request.basic_auth(username, password.as_ref()),
+            HttpAuth::Bearer(token) => request.bearer_auth(token),
+            HttpAuth::OAuth2(credentials) => {
+                let mut token = self.token.lock().await;
+                if !token.as_ref().is_some_and(AccessToken::is_fresh) {
+                    *token = Some(fetch_token(client, credentials).await?);
+                }
+                request.bearer_auth(&token.as_ref().expect("token was just fetched").value)
+            }
+        })
+    }
+
+    /// Forgets the cached token, after the server rejected it.
+    pub(crate) async fn invalidate(&self) {
+        *self.token.lock().await = None;
+    }
+}
+
+async fn fetch_token(client: &Client, credentials: &OAuth2ClientCredentials) -> Result<AccessToken, HttpClientError> {
+    let mut form = vec![
+        ("grant_type", "client_credentials".to_string()),
+        ("client_id", credentials.client_id.clone()),
+        ("client_secret", credentials.client_secret.clone()),
+    ];
+    if !credentials.scopes.is_empty() {
+        form.push(("scope", credentials.scopes.join(" ")));
+    }
+
+    let response = client.post(&credentials.token_url).form(&form).send().await?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(HttpClientError::AuthError(format!("Token request to {} failed: {}", credentials.token_url, status)));
+    }
+    let token: TokenResponse = response.json().await
+        .map_err(|e| HttpClientError::AuthError(format!("Invalid token response from {}: {}", credentials.token_url, e)))?;
+
+    Ok(AccessToken {
+        value: token.access_token,
+        expires_at: token.expires_in.map(|expires_in| Instant::now() + Duration::from_secs(expires_in)),
+    })
+}
@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use rand::distributions::{Alphanumeric, DistString};
+use serde_json::Value as JsonValue;
+use super::{xml, HttpClientError};
+
+/// A request body ready to send.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodedBody {
+    pub content: Vec<u8>,
+    /// Replaces the `Content-Type` of the request, for encodings adding
+    /// parameters to it such as a multipart boundary.
+    pub content_type: Option<String>,
+}
+
+impl EncodedBody {
+    pub fn new(content: impl Into<Vec<u8>>) -> Self {
+        EncodedBody { content: content.into(), content_type: None }
+    }
+}
+
+/// Encodes request bodies for a media type. Closures taking the body and
+/// the full `Content-Type` are encoders too.
+pub trait BodyEncoder: Send + Sync {
+    fn encode(&self, body: &JsonValue, content_type: &str) -> Result<EncodedBody, String>;
+}
+
+impl<F> BodyEncoder for F
+where
+    F: Fn(&JsonValue, &str) -> Result<EncodedBody, String> + Send + Sync,
+{
+    fn encode(&self, body: &JsonValue, content_type: &str) -> Result<EncodedBody, String> {
+        self(body, content_type)
+    }
+}
+
+/// The encoders of an `HttpClient`, by media type. Types with a `+json` or
+/// `+xml` suffix fall back to the JSON and XML encoders.
+#[derive(Clone)]
+pub struct BodyEncoders {
+    encoders: HashMap<String, Arc<dyn BodyEncoder>>,
+}
+
+impl BodyEncoders {
+    /// JSON, NDJSON, forms, multipart forms, text, CSV, XML and raw bytes.
+    pub fn new() -> Self {
+        let mut encoders = BodyEncoders { encoders: HashMap::new() };
+        encoders.insert("application/json", |body: &JsonValue, _: &str| encode_json(body));
+        encoders.insert("application/x-ndjson", |body: &JsonValue, _: &str| encode_ndjson(body));
+        encoders.insert("application/jsonl", |body: &JsonValue, _: &str| encode_ndjson(body));
+        encoders.insert("application/x-www-form-urlencoded", |body: &JsonValue, _: &str| {
+            serde_urlencoded::to_string(body).map(EncodedBody::new).map_err(|e| e.to_string())
+        });
+        encoders.insert("multipart/form-data", encode_multipart);
+        encoders.insert("text/plain", |body: &JsonValue, _: &str| Ok(EncodedBody::new(text(body))));
+        encoders.insert("text/csv", |body: &JsonValue, _: &str| encode_csv(body));
+        encoders.insert("application/xml", |body: &JsonValue, _: &str| encode_xml(body));
+        encoders.insert("text/xml", |body: &JsonValue, _: &str| encode_xml(body));
+        encoders.insert("application/octet-stream", |body: &JsonValue, _: &str| encode_raw(body));
+        encoders
+    }
+
+    /// Encodes bodies of media type `mime`, such as `text/csv`, with
+    /// `encoder` from now on.
+    pub fn insert(&mut self, mime: &str, encoder: impl BodyEncoder + 'static) {
+        self.encoders.insert(mime.to_ascii_lowercase(), Arc::new(encoder));
+    }
+
+    pub(crate) fn encode(&self, body: &JsonValue, content_type: &str) -> Result<EncodedBody, HttpClientError> {
+        let mime = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+        let encoder = self.encoders.get(&mime)
+            .or_else(|| mime.ends_with("+json").then(|| self.encoders.get("application/json")).flatten())
+            .or_else(|| mime.ends_with("+xml").then(|| self.encoders.get("application/xml")).flatten())
+            .ok_or_else(|| HttpClientError::UnsupportedContentType(content_type.to_string()))?;
+        encoder.encode(body, content_type)
+            .map_err(|e| HttpClientError::EncodeError(format!("{} body: {}", mime, e)))
+    }
+}
+
+impl Default for BodyEncoders {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for BodyEncoders {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut mimes: Vec<&String> = self.encoders.keys().collect();
+        mimes.sort();
+        f.debug_tuple("BodyEncoders").field(&mimes).finish()
+    }
+}
+
+/// Strings as they are, anything else as JSON.
+fn text(value: &JsonValue) -> String {
+    match value {
+        JsonValue::String(text) => text.clone(),
+        JsonValue::Null => String::new(),
+        value => value.to_string(),
+    }
+}
+
+fn encode_json(body: &JsonValue) -> Result<EncodedBody, String> {
+    serde_json::to_vec(body).map(EncodedBody::new).map_err(|e| e.to_string())
+}
+
+/// An array as one JSON document per line; anything else as a single line.
+fn encode_ndjson(body: &JsonValue) -> Result<EncodedBody, String> {
+    let lines = match body {
+        JsonValue::Array(values) => values.iter().collect(),
+        value => vec![value],
+    };
+    let mut content = Vec::new();
+    for line in lines {
+        serde_json::to_writer(&mut content, line).map_err(|e| e.to_string())?;
+        content.push(b'\n');
+    }
+    Ok(EncodedBody::new(content))
+}
+
+/// An array of rows: objects, under a header row of their keys, or arrays.
+fn encode_csv(body: &JsonValue) -> Result<EncodedBody, String> {
+    let JsonValue::Array(rows) = body else {
+        return Err("expected an array of rows".to_string());
+    };
+    let mut header: Vec<&String> = Vec::new();
+    for row in rows {
+        if let JsonValue::Object(fields) = row {
+            for key in fields.keys() {
+                if !header.contains(&key) {
+                    header.push(key);
+                }
+            }
+        }
+    }
+
+    let mut writer = csv::WriterBuilder::new()
+        .flexible(true)
+        .terminator(csv::Terminator::CRLF)
+        .from_writer(Vec::new());
+    if !header.is_empty() {
+        writer.write_record(&header).map_err(|e| e.to_string())?;
+    }
+    for row in rows {
+        let record: Vec<String> = match row {
+            JsonValue::Object(fields) => header.iter().map(|key| fields.get(key.as_str()).map(text).unwrap_or_default()).collect(),
+            JsonValue::Array(values) => values.iter().map(text).collect(),
+            _ => return Err("expected rows to be objects or arrays".to_string()),
+        };
+        writer.write_record(&record).map_err(|e| e.to_string())?;
+    }
+    writer.into_inner().map(EncodedBody::new).map_err(|e| e.to_string())
+}
+
+/// A string is taken as a ready XML document; anything else converted as
+/// `ResponseFormat::Xml` reads it.
+fn encode_xml(body: &JsonValue) -> Result<EncodedBody, String> {
+    match body {
+        JsonValue::String(document) => Ok(EncodedBody::new(document.as_bytes())),
+        value => xml::write(value).map(EncodedBody::new),
+    }
+}
+
+/// Base64-encoded bytes, as `ResponseFormat::Raw` reads them.
+fn encode_raw(body: &JsonValue) -> Result<EncodedBody, String> {
+    let JsonValue::String(encoded) = body else {
+        return Err("expected a base64 string".to_string());
+    };
+    BASE64.decode(encoded).map(EncodedBody::new).map_err(|e| e.to_string())
+}
+
+/// One part per field of an object, repeated for arrays. A field given as
+/// `{"content": ..., "filename": ..., "content_type": ...}` is sent as a
+/// file, with `content_base64` instead of `content` for binary files.
+fn encode_multipart(body: &JsonValue, content_type: &str) -> Result<EncodedBody, String> {
+    let JsonValue::Object(fields) = body else {
+        return Err("expected an object of fields".to_string());
+    };
+    let boundary = format!("metaflow-{}", Alphanumeric.sample_string(&mut rand::thread_rng(), 24));
+    let mut content = Vec::new();
+    for (name, value) in fields {
+        let values = match value {
+            JsonValue::Array(values) => values.iter().collect(),
+            value => vec![value],
+        };
+        for value in values.into_iter().filter(|value| !value.is_null()) {
+            content.extend_from_slice(format!("--{}\r\nContent-Disposition: form-data; name=\"{}\"", boundary, quote(name)).as_bytes());
+            match value {
+                JsonValue::Object(file) => {
+                    if let Some(filename) = file.get("filename").and_then(JsonValue::as_str) {
+                        content.extend_from_slice(format!("; filename=\"{}\"", quote(filename)).as_bytes());
+                    }
+                    let file_type = file.get("content_type").and_then(JsonValue::as_str).unwrap_or("application/octet-stream");
+                    content.extend_from_slice(format!("\r\nContent-Type: {}\r\n\r\n", file_type).as_bytes());
+                    match (file.get("content"), file.get("content_base64")) {
+                        (Some(text_content), None) => content.extend_from_slice(text(text_content).as_bytes()),
+                        (None, Some(JsonValue::String(encoded))) => content.extend(BASE64.decode(encoded).map_err(|e| format!("{}: {}", name, e))?),
+                        _ => return Err(format!("file {} needs either content or content_base64", name)),
+                    }
+                }
+                value => content.extend_from_slice(format!("\r\n\r\n{}", text(value)).as_bytes()),
+            }
+            content.extend_from_slice(b"\r\n");
+        }
+    }
+    content.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+
+    Ok(EncodedBody {
+        content,
+        content_type: Some(format!("{}; boundary={}", content_type.split(';').next().unwrap_or_default().trim(), boundary)),
+    })
+}
+
+fn quote(name: &str) -> String {
+    name.replace('"', "%22").replace(['\r', '\n'], " ")
+}
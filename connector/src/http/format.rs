@@ -0,0 +1,138 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Number, Value as JsonValue};
+use super::xml;
+
+/// How the body of a response is turned into the value of a `Message`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseFormat {
+    Json,
+    /// An array of objects, one per row, keyed by the header row.
+    Csv,
+    /// Elements become objects keyed by child element name, with `@name`
+    /// for attributes and `#text` for text alongside them.
+    Xml,
+    /// A string.
+    Text,
+    /// Prometheus text exposition format: an array of samples, each with
+    /// its `name`, `labels`, `value` and optional `timestamp`.
+    Prometheus,
+    /// The bytes as is, base64-encoded.
+    Raw,
+}
+
+impl ResponseFormat {
+    /// The format matching a `Content-Type` header, parameters included.
+    pub fn from_content_type(content_type: &str) -> Self {
+        let mime = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+        let is_prometheus = content_type.contains("version=0.0.4");
+        match mime.as_str() {
+            "application/json" => ResponseFormat::Json,
+            mime if mime.ends_with("+json") => ResponseFormat::Json,
+            "text/csv" => ResponseFormat::Csv,
+            "application/xml" | "text/xml" => ResponseFormat::Xml,
+            mime if mime.ends_with("+xml") => ResponseFormat::Xml,
+            "application/openmetrics-text" => ResponseFormat::Prometheus,
+            "text/plain" if is_prometheus => ResponseFormat::Prometheus,
+            mime if mime.starts_with("text/") => ResponseFormat::Text,
+            _ => ResponseFormat::Raw,
+        }
+    }
+
+    pub(crate) fn decode(&self, body: &[u8]) -> Result<JsonValue, String> {
+        match self {
+            ResponseFormat::Json => serde_json::from_slice(body).map_err(|e| e.to_string()),
+            ResponseFormat::Csv => parse_csv(body),
+            ResponseFormat::Xml => xml::parse(&text(body)?),
+            ResponseFormat::Text => Ok(JsonValue::String(String::from_utf8_lossy(body).into_owned())),
+            ResponseFormat::Prometheus => parse_prometheus(&text(body)?),
+            ResponseFormat::Raw => Ok(JsonValue::String(BASE64.encode(body))),
+        }
+    }
+}
+
+fn text(body: &[u8]) -> Result<String, String> {
+    String::from_utf8(body.to_vec()).map_err(|e| e.to_string())
+}
+
+/// CSV with a header row. Values are kept as strings.
+fn parse_csv(body: &[u8]) -> Result<JsonValue, String> {
+    let mut reader = csv::Reader::from_reader(body);
+    let header = reader.headers().map_err(|e| e.to_string())?.clone();
+    let rows = reader.records()
+        .map(|record| {
+            let record = record.map_err(|e| e.to_string())?;
+            Ok(header.iter().zip(record.iter()).map(|(key, value)| (key.to_string(), value.into())).collect::<Map<_, _>>().into())
+        })
+        .collect::<Result<_, String>>()?;
+    Ok(JsonValue::Array(rows))
+}
+
+/// Reads the samples of a Prometheus exposition. Values that are not
+/// finite, such as `NaN` or `+Inf`, are kept as strings.
+fn parse_prometheus(text: &str) -> Result<JsonValue, String> {
+    let mut samples = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = || format!("invalid Prometheus sample on line {}: {}", number + 1, line);
+
+        let (name, labels, rest) = match line.find(['{', ' ']) {
+            Some(start) if line[start..].starts_with('{') => {
+                let (labels, rest) = parse_labels(&line[start + 1..]).ok_or_else(invalid)?;
+                (&line[..start], labels, rest)
+            }
+            Some(start) => (&line[..start], Map::new(), &line[start..]),
+            None => return Err(invalid()),
+        };
+        let mut rest = rest.split_whitespace();
+        let value = rest.next().ok_or_else(invalid)?;
+        let value = match value.parse::<f64>().ok().and_then(Number::from_f64) {
+            Some(number) => JsonValue::Number(number),
+            None if value.parse::<f64>().is_ok() => JsonValue::String(value.to_string()),
+            None => return Err(invalid()),
+        };
+
+        let mut sample = Map::new();
+        sample.insert("name".to_string(), name.into());
+        sample.insert("labels".to_string(), labels.into());
+        sample.insert("value".to_string(), value);
+        if let Some(timestamp) = rest.next() {
+            sample.insert("timestamp".to_string(), timestamp.parse::<i64>().map_err(|_| invalid())?.into());
+        }
+        samples.push(JsonValue::Object(sample));
+    }
+    Ok(JsonValue::Array(samples))
+}
+
+/// Parses `name="value",...}` and returns the labels with what follows
+/// the closing brace.
+fn parse_labels(text: &str) -> Option<(Map<String, JsonValue>, &str)> {
+    let mut labels = Map::new();
+    let mut rest = text.trim_start();
+    loop {
+        if let Some(after) = rest.strip_prefix('}') {
+            return Some((labels, after));
+        }
+        let (name, after) = rest.split_once('=')?;
+        let mut chars = after.trim_start().strip_prefix('"')?.char_indices();
+        let mut value = String::new();
+        let end = loop {
+            match chars.next()? {
+                (_, '\\') => match chars.next()?.1 {
+                    'n' => value.push('\n'),
+                    c => value.push(c),
+                },
+                (index, '"') => break index,
+                (_, c) => value.push(c),
+            }
+        };
+        labels.insert(name.trim().trim_start_matches(',').trim().to_string(), value.into());
+        let after = after.trim_start()[1 + end + 1..].trim_start();
+        rest = after.strip_prefix(',').unwrap_or(after).trim_start();
+    }
+}
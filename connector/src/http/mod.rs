@@ -0,0 +1,352 @@
+use reqwest::header::{HeaderMap, CONTENT_TYPE, RETRY_AFTER};
+use reqwest::{Client, Method, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use crate::connector::{DataConnector, DataConnectorError};
+use crate::Message;
+use async_trait::async_trait;
+use serde_json::Value as JsonValue;
+use thiserror::Error;
+use log::{debug, warn};
+use tokio::time::sleep;
+
+mod auth;
+pub use auth::{HttpAuth, OAuth2ClientCredentials};
+use auth::Authenticator;
+
+mod encoding;
+pub use encoding::{BodyEncoder, BodyEncoders, EncodedBody};
+
+mod format;
+pub use format::ResponseFormat;
+
+mod xml;
+
+mod pagination;
+pub use pagination::{PageEmission, Pagination, PaginationStrategy};
+
+mod retry;
+pub use retry::HttpRetryPolicy;
+use retry::parse_retry_after;
+
+#[derive(Debug, Error)]
+pub enum HttpClientError {
+    #[error("Request error: {0}")]
+    RequestError(#[from] reqwest::Error),
+    #[error("Timeout error: {0}")]
+    TimeoutError(String),
+    #[error("Failed to deserialize response: {0}")]
+    DeserializeError(String),
+    #[error("Invalid HTTP method: {0}")]
+    InvalidMethodError(String),
+    #[error("HTTP error: {0}")]
+    HttpError(String),
+    #[error("Authentication error: {0}")]
+    AuthError(String),
+    #[error("Unsupported request Content-Type: {0}")]
+    UnsupportedContentType(String),
+    #[error("Failed to encode request body: {0}")]
+    EncodeError(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HttpRequest<T> {
+    pub method: String,
+    pub url: String,
+    pub body: Option<T>,
+    pub headers: Option<HashMap<String, String>>,
+    pub query_params: Option<HashMap<String, String>>,
+    pub timeout_duration: Option<Duration>,
+    /// Retries failed attempts; without a policy the first failure is
+    /// returned.
+    #[serde(default)]
+    pub retry: Option<HttpRetryPolicy>,
+    /// Reads every page of a paged API instead of just the first; only
+    /// honored by `DataConnector::read_all`.
+    #[serde(default)]
+    pub pagination: Option<Pagination>,
+    /// How to read the response body, whatever its `Content-Type` says.
+    #[serde(default)]
+    pub response_format: Option<ResponseFormat>,
+}
+
+impl<T> HttpRequest<T> {
+    /// A request with no body, headers, query parameters, timeout, retries,
+    /// pagination or response format.
+    pub fn new(method: &str, url: &str) -> Self {
+        HttpRequest {
+            method: method.to_string(),
+            url: url.to_string(),
+            body: None,
+            headers: None,
+            query_params: None,
+            timeout_duration: None,
+            retry: None,
+            pagination: None,
+            response_format: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct HttpResponse<T> {
+    pub status: u16,
+    pub body: Option<T>,
+    /// The `Content-Type` the body was sent with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct HttpClient {
+    client: Client,
+    auth: Option<Arc<Authenticator>>,
+    encoders: BodyEncoders,
+}
+
+impl HttpClient {
+    pub fn new() -> Self {
+        HttpClient {
+            client: Client::new(),
+            auth: None,
+            encoders: BodyEncoders::new(),
+        }
+    }
+
+    /// Authenticates every request with `auth`, which takes precedence over
+    /// an `Authorization` header of the request.
+    pub fn with_auth(mut self, auth: HttpAuth) -> Self {
+        self.auth = Some(Arc::new(Authenticator::new(auth)));
+        self
+    }
+
+    /// Encodes request bodies of media type `mime` with `encoder`, in place
+    /// of the built-in encoder if there is one.
+    pub fn with_body_encoder(mut self, mime: &str, encoder: impl BodyEncoder + 'static) -> Self {
+        self.encoders.insert(mime, encoder);
+        self
+    }
+
+    pub async fn request<QueryBody: Serialize, ResponseBody: DeserializeOwned>(&self, request: HttpRequest<QueryBody>) -> Result<HttpResponse<ResponseBody>, HttpClientError> {
+        let (response, _) = self.fetch(&request).await?;
+        Ok(response)
+    }
+
+    /// Requests every page, as told by `pagination`.
+    pub async fn paginate<QueryBody: Serialize + Clone>(&self, request: HttpRequest<QueryBody>, pagination: &Pagination) -> Result<Vec<HttpResponse<JsonValue>>, HttpClientError> {
+        let mut pages = Vec::new();
+        let mut next = Some(pagination.first_request(&request));
+        while let Some(request) = next.take() {
+            if pages.len() >= pagination.max_pages {
+                warn!("Stopped paging {} after {} pages", request.url, pages.len());
+                break;
+            }
+            let (page, headers) = self.fetch::<QueryBody, JsonValue>(&request).await?;
+            next = pagination.next_request(&request, page.body.as_ref(), &headers);
+            // An empty last page adds nothing, unless it is the only one
+            if pages.is_empty() || !pagination.is_empty(page.body.as_ref()) {
+                pages.push(page);
+            }
+        }
+        Ok(pages)
+    }
+
+    /// Sends `request`, retrying as its policy allows, and reads the
+    /// response.
+    async fn fetch<QueryBody: Serialize, ResponseBody: DeserializeOwned>(&self, request: &HttpRequest<QueryBody>) -> Result<(HttpResponse<ResponseBody>, HeaderMap), HttpClientError> {
+        let started = Instant::now();
+        let mut attempts = 1;
+        let response = loop {
+            let (error, retryable, retry_after) = match self.send_authorized(request).await {
+                Ok(response) if response.status().is_success() => break response,
+                Ok(response) => {
+                    let status = response.status();
+                    let retry_after = response.headers().get(RETRY_AFTER)
+                        .and_then(|value| value.to_str().ok())
+                        .and_then(|value| parse_retry_after(value, SystemTime::now()));
+                    let retryable = status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS;
+                    (HttpClientError::HttpError(format!("HTTP error: {}", status)), retryable, retry_after)
+                }
+                Err(e) => {
+                    let retryable = match &e {
+                        HttpClientError::TimeoutError(_) => true,
+                        HttpClientError::RequestError(e) => e.is_connect() || e.is_timeout() || e.is_request(),
+                        _ => false,
+                    };
+                    (e, retryable, None)
+                }
+            };
+
+            let delay = match &request.retry {
+                Some(retry) if retryable => retry.delay(attempts, retry_after, started.elapsed()),
+                _ => None,
+            };
+            let Some(delay) = delay else {
+                return Err(error);
+            };
+            debug!("Retrying {} {} in {:?} after attempt {} failed: {}", request.method, request.url, delay, attempts, error);
+            sleep(delay).await;
+            attempts += 1;
+        };
+
+        let status = response.status();
+        let headers = response.headers().clone();
+        let content_type = headers.get(CONTENT_TYPE).and_then(|value| value.to_str().ok()).map(str::to_string);
+        
+        let response_body = response.bytes().await.map_err(|e| {
+            if e.is_timeout() {
+                HttpClientError::TimeoutError("Response timed out".to_string())
+            } else {
+                HttpClientError::RequestError(e)
+            }
+        })?;
+        
+        let body = if response_body.is_empty() {
+            None
+        } else {
+            // Bodies without a Content-Type have always been read as JSON
+            let format = request.response_format
+                .or_else(|| content_type.as_deref().map(ResponseFormat::from_content_type))
+                .unwrap_or(ResponseFormat::Json);
+            let parsed_body = match format {
+                ResponseFormat::Json => serde_json::from_slice::<ResponseBody>(&response_body)
+                    .map_err(|_| String::from_utf8_lossy(&response_body).into_owned()),
+                format => format.decode(&response_body)
+                    .and_then(|value| serde_json::from_value(value).map_err(|e| e.to_string())),
+            };
+            Some(parsed_body.map_err(HttpClientError::DeserializeError)?)
+        };
+        
+        Ok((HttpResponse {
+            status: status.as_u16(),
+            body,
+            content_type,
+        }, headers))
+    }
+
+    /// Sends `request`, once more with a fresh token if the server rejected
+    /// a cached one.
+    async fn send_authorized<QueryBody: Serialize>(&self, request: &HttpRequest<QueryBody>) -> Result<Response, HttpClientError> {
+        let response = self.send(request).await?;
+        if response.status() == StatusCode::UNAUTHORIZED {
+            if let Some(auth) = self.auth.as_ref().filter(|auth| auth.renews_tokens()) {
+                // The token may have been revoked before its expiry
+                auth.invalidate().await;
+                return self.send(request).await;
+            }
+        }
+        Ok(response)
+    }
+
+    async fn send<QueryBody: Serialize>(&self, request: &HttpRequest<QueryBody>) -> Result<Response, HttpClientError> {
+        let method: Method = request.method.parse().map_err(|_| HttpClientError::InvalidMethodError(request.method.to_string()))?;
+        let mut request_builder = self.client.request(method.clone(), &request.url);
+
+        if let Some(ref h) = request.headers {
+            for (key, value) in h {
+                if self.auth.is_some() && key.eq_ignore_ascii_case("Authorization") {
+                    continue;
+                }
+                // Set along with the encoded body
+                if request.body.is_some() && key.eq_ignore_ascii_case("Content-Type") {
+                    continue;
+                }
+                request_builder = request_builder.header(key, value);
+            }
+        }
+
+        if let Some(params) = &request.query_params {
+            request_builder = request_builder.query(params);
+        }
+
+        if let Some(b) = &request.body {
+            let content_type = request.headers.iter().flatten()
+                .find(|(key, _)| key.eq_ignore_ascii_case("Content-Type"))
+                .map_or("application/json", |(_, value)| value.as_str());
+            let body = serde_json::to_value(b).map_err(|e| HttpClientError::EncodeError(e.to_string()))?;
+            let encoded = self.encoders.encode(&body, content_type)?;
+            request_builder = request_builder
+                .header(CONTENT_TYPE, encoded.content_type.as_deref().unwrap_or(content_type))
+                .body(encoded.content);
+        }
+
+        if let Some(duration) = request.timeout_duration {
+            request_builder = request_builder.timeout(duration);
+        }
+
+        if let Some(auth) = &self.auth {
+            request_builder = auth.authorize(&self.client, request_builder).await?;
+        }
+
+        let request = request_builder.build()?;
+        self.client.execute(request).await.map_err(|e| {
+            if e.is_timeout() {
+                HttpClientError::TimeoutError("Request timed out".to_string())
+            } else {
+                HttpClientError::RequestError(e)
+            }
+        })
+    }
+}
+
+impl Default for HttpClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl DataConnector for HttpClient {
+
+    async fn write(&self, data: Message) -> Result<Message, DataConnectorError> {
+        let http_request = HttpRequest::<JsonValue>::deserialize(data.payload.value())
+            .map_err(|e| HttpClientError::DeserializeError(e.to_string()))?;
+        let response: HttpResponse<JsonValue> = self.request(http_request).await?;
+        let response_payload = serde_json::to_value(response).map_err(|e| HttpClientError::DeserializeError(e.to_string()))?;
+
+        Ok(Message {
+            compression: data.compression,
+            payload: data.payload.with_value(response_payload),
+            envelope: data.envelope.derive(),
+        })
+    }
+
+    async fn read(&self, data: Message) -> Result<Message, DataConnectorError> {
+        self.write(data).await
+    }
+
+    async fn read_all(&self, data: Message) -> Result<Vec<Message>, DataConnectorError> {
+        let mut http_request = HttpRequest::<JsonValue>::deserialize(data.payload.value())
+            .map_err(|e| HttpClientError::DeserializeError(e.to_string()))?;
+        let Some(pagination) = http_request.pagination.take() else {
+            return Ok(vec![self.read(data).await?]);
+        };
+
+        let mut pages = self.paginate(http_request, &pagination).await?;
+        if pagination.emit == PageEmission::Merged {
+            let status = pages.last().map_or(200, |page| page.status);
+            let content_type = pages.last().and_then(|page| page.content_type.clone());
+            let items = pages.iter()
+                .flat_map(|page| match pagination.items(page.body.as_ref()) {
+                    Some(items) => items.clone(),
+                    None => page.body.clone().into_iter().collect(),
+                })
+                .collect();
+            pages = vec![HttpResponse { status, body: Some(JsonValue::Array(items)), content_type }];
+        }
+
+        pages.into_iter()
+            .map(|page| {
+                let response_payload = serde_json::to_value(page).map_err(|e| HttpClientError::DeserializeError(e.to_string()))?;
+                Ok(Message {
+                    compression: data.compression.clone(),
+                    payload: data.payload.with_value(response_payload),
+                    envelope: data.envelope.derive(),
+                })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,168 @@
+use reqwest::header::{HeaderMap, LINK};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use super::HttpRequest;
+
+/// How `HttpClient` follows a paged API from one page to the next. Paging
+/// stops at the first empty or terminal page, or after `max_pages`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Pagination {
+    #[serde(flatten)]
+    pub strategy: PaginationStrategy,
+    /// Dotted path to the array of items in a page, such as `data.items`;
+    /// the page itself when `None`. A page without items, or where the path
+    /// leads nowhere, is the last.
+    #[serde(default)]
+    pub items: Option<String>,
+    #[serde(default)]
+    pub emit: PageEmission,
+    #[serde(default = "default_max_pages")]
+    pub max_pages: usize,
+}
+
+fn default_max_pages() -> usize {
+    100
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PaginationStrategy {
+    /// Follows the `rel="next"` URL of the RFC 5988 `Link` header.
+    LinkHeader,
+    /// Sends the cursor found at `cursor_path` of a page as the
+    /// `cursor_param` query parameter of the next; no cursor ends paging.
+    Cursor { cursor_path: String, cursor_param: String },
+    /// Moves `offset_param` forward by `limit` until a page comes back
+    /// short, or is not an array that can be counted.
+    OffsetLimit {
+        offset_param: String,
+        limit_param: String,
+        limit: u64,
+        #[serde(default)]
+        start: u64,
+    },
+    PageNumber {
+        page_param: String,
+        #[serde(default = "default_first_page")]
+        first_page: u64,
+    },
+}
+
+fn default_first_page() -> u64 {
+    1
+}
+
+/// Whether the pages are read as one message or one message each.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PageEmission {
+    /// One message whose body is the array of the items of all pages.
+    #[default]
+    Merged,
+    PerPage,
+}
+
+impl Pagination {
+    /// Sets the query parameters selecting the first page.
+    pub(crate) fn first_request<T: Clone>(&self, request: &HttpRequest<T>) -> HttpRequest<T> {
+        match &self.strategy {
+            PaginationStrategy::OffsetLimit { offset_param, limit_param, limit, start } => {
+                let request = with_param(request, offset_param, start.to_string());
+                with_param(&request, limit_param, limit.to_string())
+            }
+            PaginationStrategy::PageNumber { page_param, first_page } => with_param(request, page_param, first_page.to_string()),
+            PaginationStrategy::LinkHeader | PaginationStrategy::Cursor { .. } => request.clone(),
+        }
+    }
+
+    /// The items of a page, if it holds an array of them.
+    pub(crate) fn items<'a>(&self, body: Option<&'a JsonValue>) -> Option<&'a Vec<JsonValue>> {
+        let body = body?;
+        match &self.items {
+            Some(path) => lookup(body, path)?.as_array(),
+            None => body.as_array(),
+        }
+    }
+
+    /// Whether a page has nothing in it, which makes it the last.
+    pub(crate) fn is_empty(&self, body: Option<&JsonValue>) -> bool {
+        match self.items(body) {
+            Some(items) => items.is_empty(),
+            None => body.is_none() || self.items.is_some(),
+        }
+    }
+
+    /// The request for the page after the one `request` returned, or `None`
+    /// if that was the last.
+    pub(crate) fn next_request<T: Clone>(&self, request: &HttpRequest<T>, body: Option<&JsonValue>, headers: &HeaderMap) -> Option<HttpRequest<T>> {
+        if self.is_empty(body) {
+            return None;
+        }
+        let items = self.items(body);
+
+        match &self.strategy {
+            PaginationStrategy::LinkHeader => {
+                let url = next_link(headers, &request.url)?;
+                Some(HttpRequest { url, query_params: None, ..request.clone() })
+            }
+            PaginationStrategy::Cursor { cursor_path, cursor_param } => {
+                let cursor = match lookup(body?, cursor_path)? {
+                    JsonValue::String(cursor) if !cursor.is_empty() => cursor.clone(),
+                    JsonValue::Number(cursor) => cursor.to_string(),
+                    _ => return None,
+                };
+                Some(with_param(request, cursor_param, cursor))
+            }
+            PaginationStrategy::OffsetLimit { offset_param, limit, start, .. } => {
+                if items.is_none_or(|items| (items.len() as u64) < *limit) {
+                    return None;
+                }
+                let offset = param(request, offset_param).unwrap_or(*start);
+                Some(with_param(request, offset_param, (offset + limit).to_string()))
+            }
+            PaginationStrategy::PageNumber { page_param, first_page } => {
+                let page = param(request, page_param).unwrap_or(*first_page);
+                Some(with_param(request, page_param, (page + 1).to_string()))
+            }
+        }
+    }
+}
+
+fn param<T>(request: &HttpRequest<T>, name: &str) -> Option<u64> {
+    request.query_params.as_ref()?.get(name)?.parse().ok()
+}
+
+fn with_param<T: Clone>(request: &HttpRequest<T>, name: &str, value: String) -> HttpRequest<T> {
+    let mut query_params = request.query_params.clone().unwrap_or_default();
+    query_params.insert(name.to_string(), value);
+    HttpRequest { query_params: Some(query_params), ..request.clone() }
+}
+
+/// Follows a dotted path such as `meta.next` or `pages.0.cursor`.
+fn lookup<'a>(value: &'a JsonValue, path: &str) -> Option<&'a JsonValue> {
+    path.split('.').try_fold(value, |value, key| match value {
+        JsonValue::Array(values) => values.get(key.parse::<usize>().ok()?),
+        _ => value.get(key),
+    })
+}
+
+/// The `rel="next"` target of the `Link` headers, resolved against `base`.
+fn next_link(headers: &HeaderMap, base: &str) -> Option<String> {
+    let link = headers.get_all(LINK).iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .find_map(|link| {
+            let mut parts = link.split(';');
+            let target = parts.next()?.trim().strip_prefix('<')?.strip_suffix('>')?;
+            let is_next = parts.any(|param| {
+                let Some((name, value)) = param.split_once('=') else {
+                    return false;
+                };
+                name.trim().eq_ignore_ascii_case("rel")
+                    && value.trim().trim_matches('"').split_whitespace().any(|rel| rel.eq_ignore_ascii_case("next"))
+            });
+            is_next.then_some(target)
+        })?;
+    Some(Url::parse(base).and_then(|base| base.join(link)).map_or_else(|_| link.to_string(), String::from))
+}
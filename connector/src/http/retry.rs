@@ -0,0 +1,83 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+
+/// How `HttpClient::request` retries connection errors, timeouts, 5xx and
+/// 429 responses: up to `max_attempts` in all, waiting as long as the
+/// server asks through `Retry-After`, or else `initial_backoff` growing by
+/// `multiplier` up to `max_backoff`. No attempt is made once the waits
+/// would add up to more than `max_elapsed`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HttpRetryPolicy {
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub multiplier: f64,
+    pub max_elapsed: Duration,
+}
+
+impl Default for HttpRetryPolicy {
+    fn default() -> Self {
+        HttpRetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            multiplier: 2.0,
+            max_elapsed: Duration::from_secs(60),
+        }
+    }
+}
+
+impl HttpRetryPolicy {
+    /// How long to wait before the attempt following `attempts` failed
+    /// ones, `elapsed` after the first one started; `None` to give up.
+    pub fn delay(&self, attempts: u32, retry_after: Option<Duration>, elapsed: Duration) -> Option<Duration> {
+        if attempts >= self.max_attempts {
+            return None;
+        }
+        let delay = retry_after.unwrap_or_else(|| {
+            let exponent = attempts.saturating_sub(1).min(i32::MAX as u32) as i32;
+            let backoff = self.initial_backoff.as_secs_f64() * self.multiplier.powi(exponent);
+            Duration::from_secs_f64(backoff.min(self.max_backoff.as_secs_f64()))
+        });
+        (elapsed + delay <= self.max_elapsed).then_some(delay)
+    }
+}
+
+/// Reads a `Retry-After` value: either a number of seconds or an
+/// IMF-fixdate such as `Wed, 21 Oct 2015 07:28:00 GMT`.
+pub(crate) fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let at = UNIX_EPOCH + Duration::from_secs(parse_imf_fixdate(value)?);
+    Some(at.duration_since(now).unwrap_or_default())
+}
+
+/// Seconds since the epoch of an IMF-fixdate.
+fn parse_imf_fixdate(value: &str) -> Option<u64> {
+    let (_, date) = value.split_once(", ")?;
+    let parts: Vec<&str> = date.split(' ').collect();
+    let [day, month, year, time, "GMT"] = parts[..] else {
+        return None;
+    };
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+    let month = MONTHS.iter().position(|name| *name == month)? as u64 + 1;
+    let day: u64 = day.parse().ok()?;
+    let year: u64 = year.parse().ok()?;
+    let mut time = time.split(':').map(|part| part.parse::<u64>().ok());
+    let (hours, minutes, seconds) = (time.next()??, time.next()??, time.next()??);
+    if year < 1970 || !(1..=31).contains(&day) || hours > 23 || minutes > 59 || seconds > 60 {
+        return None;
+    }
+
+    // Days from civil, counting years from March so leap days come last
+    let (year, month) = if month <= 2 { (year - 1, month + 9) } else { (year, month - 3) };
+    let era = year / 400;
+    let year_of_era = year % 400;
+    let day_of_year = (153 * month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+    Some(days * 86400 + hours * 3600 + minutes * 60 + seconds)
+}
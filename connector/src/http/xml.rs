@@ -0,0 +1,169 @@
+use quick_xml::events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event};
+use quick_xml::{Reader, Writer};
+use serde_json::{Map, Value as JsonValue};
+
+/// Elements nested deeper than this are refused, so that a hostile or
+/// broken document cannot exhaust the stack of whoever handles the value.
+const MAX_DEPTH: usize = 128;
+
+/// Converts an XML document into a value tree, `{"root": ...}`. An element
+/// with neither attributes nor children becomes its text, or `null` when
+/// empty; any other an object of its attributes as `@name`, its children by
+/// name, in an array when repeated, and its text as `#text`.
+pub(crate) fn parse(text: &str) -> Result<JsonValue, String> {
+    let mut reader = Reader::from_str(text);
+    let error = |reader: &Reader<&[u8]>, message: String| format!("invalid XML at byte {}: {}", reader.buffer_position(), message);
+    // Elements still open, innermost last
+    let mut open: Vec<Element> = Vec::new();
+    let mut root = None;
+
+    loop {
+        let event = reader.read_event().map_err(|e| error(&reader, e.to_string()))?;
+        let (name, value) = match event {
+            Event::Start(start) => {
+                if open.len() >= MAX_DEPTH {
+                    return Err(error(&reader, format!("elements nested deeper than {}", MAX_DEPTH)));
+                }
+                open.push(Element::new(&start).map_err(|e| error(&reader, e))?);
+                continue;
+            }
+            Event::Empty(start) => {
+                let element = Element::new(&start).map_err(|e| error(&reader, e))?;
+                (element.name, value(element.fields, element.text))
+            }
+            Event::End(_) => {
+                let element = open.pop().expect("the reader checks that end tags match");
+                (element.name, value(element.fields, element.text))
+            }
+            Event::Text(text) => {
+                let text = text.unescape().map_err(|e| error(&reader, e.to_string()))?;
+                match open.last_mut() {
+                    Some(element) => element.text.push_str(&text),
+                    None if text.trim().is_empty() => {}
+                    None => return Err(error(&reader, "text outside the root element".to_string())),
+                }
+                continue;
+            }
+            Event::CData(data) => {
+                let data = std::str::from_utf8(&data).map_err(|e| error(&reader, e.to_string()))?;
+                match open.last_mut() {
+                    Some(element) => element.text.push_str(data),
+                    None => return Err(error(&reader, "text outside the root element".to_string())),
+                }
+                continue;
+            }
+            Event::Eof => break,
+            Event::Comment(_) | Event::Decl(_) | Event::PI(_) | Event::DocType(_) => continue,
+        };
+
+        match open.last_mut() {
+            Some(parent) => add_child(&mut parent.fields, name, value),
+            None if root.is_none() => root = Some((name, value)),
+            None => return Err(error(&reader, "content after the root element".to_string())),
+        }
+    }
+
+    if let Some(element) = open.last() {
+        return Err(format!("invalid XML: <{}> is not closed", element.name));
+    }
+    let (name, value) = root.ok_or("invalid XML: no root element")?;
+    let mut root = Map::new();
+    root.insert(name, value);
+    Ok(JsonValue::Object(root))
+}
+
+struct Element {
+    name: String,
+    fields: Map<String, JsonValue>,
+    text: String,
+}
+
+impl Element {
+    /// An element holding the attributes of its start tag.
+    fn new(start: &BytesStart) -> Result<Self, String> {
+        let mut fields = Map::new();
+        for attribute in start.attributes() {
+            let attribute = attribute.map_err(|e| e.to_string())?;
+            let name = String::from_utf8_lossy(attribute.key.as_ref());
+            let value = attribute.unescape_value().map_err(|e| e.to_string())?;
+            fields.insert(format!("@{}", name), value.into_owned().into());
+        }
+        Ok(Element { name: String::from_utf8_lossy(start.name().as_ref()).into_owned(), fields, text: String::new() })
+    }
+}
+
+/// Adds a child element, turning repeated ones into an array.
+fn add_child(fields: &mut Map<String, JsonValue>, name: String, value: JsonValue) {
+    match fields.get_mut(&name) {
+        Some(JsonValue::Array(values)) => values.push(value),
+        Some(existing) => *existing = JsonValue::Array(vec![existing.take(), value]),
+        None => {
+            fields.insert(name, value);
+        }
+    }
+}
+
+fn value(mut fields: Map<String, JsonValue>, text: String) -> JsonValue {
+    let text = text.trim();
+    if fields.is_empty() {
+        return if text.is_empty() { JsonValue::Null } else { text.into() };
+    }
+    if !text.is_empty() {
+        fields.insert("#text".to_string(), text.into());
+    }
+    JsonValue::Object(fields)
+}
+
+/// Writes a value tree shaped as `parse` returns it back to XML.
+pub(crate) fn write(value: &JsonValue) -> Result<String, String> {
+    let root = match value {
+        JsonValue::Object(root) if root.len() == 1 => root.iter().next().expect("root has one entry"),
+        _ => return Err("expected an object with a single root element".to_string()),
+    };
+    if root.1.is_array() {
+        return Err("expected a single root element".to_string());
+    }
+    let mut writer = Writer::new(Vec::new());
+    writer.write_event(Event::Decl(BytesDecl::new("1.0", Some("UTF-8"), None))).map_err(|e| e.to_string())?;
+    write_element(&mut writer, root.0, root.1).map_err(|e| e.to_string())?;
+    String::from_utf8(writer.into_inner()).map_err(|e| e.to_string())
+}
+
+fn write_element(writer: &mut Writer<Vec<u8>>, name: &str, value: &JsonValue) -> std::io::Result<()> {
+    match value {
+        JsonValue::Array(values) => {
+            for value in values {
+                write_element(writer, name, value)?;
+            }
+        }
+        JsonValue::Null => writer.write_event(Event::Empty(BytesStart::new(name)))?,
+        JsonValue::Object(fields) => {
+            let mut start = BytesStart::new(name);
+            for (attribute, value) in fields.iter().filter_map(|(key, value)| Some((key.strip_prefix('@')?, value))) {
+                start.push_attribute((attribute, scalar(value).as_str()));
+            }
+            writer.write_event(Event::Start(start))?;
+            if let Some(text) = fields.get("#text") {
+                writer.write_event(Event::Text(BytesText::new(&scalar(text))))?;
+            }
+            for (child, value) in fields.iter().filter(|(key, _)| !key.starts_with('@') && *key != "#text") {
+                write_element(writer, child, value)?;
+            }
+            writer.write_event(Event::End(BytesEnd::new(name)))?;
+        }
+        value => {
+            writer.write_event(Event::Start(BytesStart::new(name)))?;
+            writer.write_event(Event::Text(BytesText::new(&scalar(value))))?;
+            writer.write_event(Event::End(BytesEnd::new(name)))?;
+        }
+    }
+    Ok(())
+}
+
+fn scalar(value: &JsonValue) -> String {
+    match value {
+        JsonValue::String(text) => text.clone(),
+        JsonValue::Null => String::new(),
+        value => value.to_string(),
+    }
+}
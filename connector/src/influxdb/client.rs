@@ -1,9 +1,10 @@
 use std::collections::HashMap;
 use crate::connector::{DataConnector, DataConnectorError};
-use crate::{Message, Protocol};
+use crate::Message;
 use crate::http::{HttpClient, HttpRequest};
 use crate::influxdb::protocol::InfluxDbDataPoint;
 use async_trait::async_trait;
+use serde::Deserialize;
 use crate::influxdb::error::InfluxDbClientError;
 
 #[derive(Clone, Debug)]
@@ -26,13 +27,37 @@ impl InfluxDbClient {
         &self,
         data: InfluxDbDataPoint
     ) -> Result<Option<String>, InfluxDbClientError> {
-        let url = format!("{}/api/v2/write", self.base_url);
         let precision = data.infer_precision();
         let data_str = data.to_line_protocol();
-        
+        self.write_lines(data.organization, data.bucket, precision, data_str).await
+    }
+
+    /// Writes several points in one request. They must share their
+    /// organization, bucket and precision.
+    pub async fn write_data_points(
+        &self,
+        data: Vec<InfluxDbDataPoint>
+    ) -> Result<Option<String>, InfluxDbClientError> {
+        let Some(first) = data.first() else {
+            return Ok(None);
+        };
+        let (organization, bucket, precision) = (first.organization.clone(), first.bucket.clone(), first.infer_precision());
+        let lines = data.iter().map(InfluxDbDataPoint::to_line_protocol).collect::<Vec<_>>().join("\n");
+        self.write_lines(organization, bucket, precision, lines).await
+    }
+
+    async fn write_lines(
+        &self,
+        organization: String,
+        bucket: String,
+        precision: String,
+        data_str: String,
+    ) -> Result<Option<String>, InfluxDbClientError> {
+        let url = format!("{}/api/v2/write", self.base_url);
+
         let mut query_params = HashMap::new();
-        query_params.insert("org".to_string(), data.organization);
-        query_params.insert("bucket".to_string(), data.bucket);
+        query_params.insert("org".to_string(), organization);
+        query_params.insert("bucket".to_string(), bucket);
         query_params.insert("precision".to_string(), precision);
 
         let mut headers = HashMap::new();
@@ -46,6 +71,9 @@ impl InfluxDbClient {
             headers: Some(headers),
             query_params: Some(query_params),
             timeout_duration: None,
+            retry: None,
+            pagination: None,
+            response_format: None,
         };
         match self.http_client.request::<String, String>(request).await {
             Ok(response) => Ok(response.body),
@@ -58,17 +86,15 @@ impl InfluxDbClient {
 impl DataConnector for InfluxDbClient {
 
     async fn write(&self, data: Message) -> Result<Message, DataConnectorError> {
-        let influx_data: InfluxDbDataPoint = match data.payload {
-            Protocol::Json(value) => {
-                serde_json::from_value(value).map_err(InfluxDbClientError::SerializationError)?
-            },
-        };
+        let influx_data = InfluxDbDataPoint::deserialize(data.payload.value())
+            .map_err(InfluxDbClientError::SerializationError)?;
         let result = self.write_data(influx_data).await?;
         let response_payload = serde_json::to_value(result).map_err(InfluxDbClientError::SerializationError)?;
 
         Ok(Message {
             compression: data.compression,
-            payload: Protocol::Json(response_payload),
+            payload: data.payload.with_value(response_payload),
+            envelope: data.envelope.derive(),
         })
     }
 
@@ -77,4 +103,47 @@ impl DataConnector for InfluxDbClient {
             InfluxDbClientError::UnsupportedValueType("Read operation is not supported".to_string())
         ))
     }
+
+    /// Sends the points bound for the same bucket, at the same precision,
+    /// in a single line protocol request.
+    async fn write_batch(&self, data: Vec<Message>) -> Vec<Result<Message, DataConnectorError>> {
+        let mut results: Vec<Option<Result<Message, DataConnectorError>>> = Vec::with_capacity(data.len());
+        let mut groups: Vec<((String, String, String), Vec<(usize, InfluxDbDataPoint)>)> = Vec::new();
+        for (index, message) in data.iter().enumerate() {
+            results.push(None);
+            match InfluxDbDataPoint::deserialize(message.payload.value()) {
+                Ok(point) => {
+                    let key = (point.organization.clone(), point.bucket.clone(), point.infer_precision());
+                    match groups.iter_mut().find(|(group, _)| *group == key) {
+                        Some((_, points)) => points.push((index, point)),
+                        None => groups.push((key, vec![(index, point)])),
+                    }
+                }
+                Err(e) => results[index] = Some(Err(InfluxDbClientError::SerializationError(e).into())),
+            }
+        }
+
+        for (_, points) in groups {
+            let (indices, points): (Vec<usize>, Vec<InfluxDbDataPoint>) = points.into_iter().unzip();
+            let written = self.write_data_points(points).await
+                .and_then(|result| serde_json::to_value(result).map_err(InfluxDbClientError::SerializationError))
+                .map_err(|e| {
+                    let e = DataConnectorError::from(e);
+                    (e.kind(), e.to_string())
+                });
+            for index in indices {
+                let message = &data[index];
+                results[index] = Some(match &written {
+                    Ok(response_payload) => Ok(Message {
+                        compression: message.compression.clone(),
+                        payload: message.payload.with_value(response_payload.clone()),
+                        envelope: message.envelope.derive(),
+                    }),
+                    Err((kind, message)) => Err(DataConnectorError::BatchError { kind: *kind, message: message.clone() }),
+                });
+            }
+        }
+
+        results.into_iter().map(|result| result.expect("every message has a result")).collect()
+    }
 }
@@ -143,11 +143,11 @@ impl InfluxDbDataPoint {
     pub fn infer_precision(&self) -> String {
         let duration = self.timestamp.duration_since(UNIX_EPOCH).expect("Time went backwards");
         let nanos = duration.as_nanos();
-        if nanos % 1_000_000_000 == 0 {
+        if nanos.is_multiple_of(1_000_000_000) {
             "s".to_string()
-        } else if nanos % 1_000_000 == 0 {
+        } else if nanos.is_multiple_of(1_000_000) {
             "ms".to_string()
-        } else if nanos % 1_000 == 0 {
+        } else if nanos.is_multiple_of(1_000) {
             "us".to_string()
         } else {
             "ns".to_string()
@@ -3,7 +3,7 @@ pub mod influxdb;
 pub mod tcp;
 
 mod protocol;
-pub use protocol::{MessageCodec, Message, Protocol, Compression};
+pub use protocol::{MessageCodec, MessageBatch, Frame, FrameCodec, LegacyMessageCodec, CodecError, Message, Envelope, Protocol, Compression, CompressionPolicy, ZstdDictionary, ZstdDictionaries, DEFAULT_MAX_FRAME_SIZE, DEFAULT_DICTIONARY_SIZE};
 
 mod connector;
-pub use connector::{Connector, DataConnector, DataConnectorError};
+pub use connector::{Connector, DataConnector, DataConnectorError, ErrorKind};
@@ -0,0 +1,66 @@
+use bytes::{Buf, BufMut};
+use rmp_serde::{from_slice as from_msgpack_slice, to_vec as to_msgpack_vec};
+
+use super::frame::{protocol_from_tag, protocol_tag};
+use super::{frame_len, CodecError, Compression, Envelope, Message};
+
+/// Messages sent together in one frame and compressed in a single pass,
+/// which pays off for runs of similar documents.
+#[derive(Debug, Clone)]
+pub struct MessageBatch {
+    /// Applied to the batch as a whole; the compression of the individual
+    /// messages is ignored.
+    pub compression: Compression,
+    pub messages: Vec<Message>,
+}
+
+impl MessageBatch {
+    pub fn new(compression: Compression, messages: Vec<Message>) -> Self {
+        MessageBatch { compression, messages }
+    }
+}
+
+/// Lays out the messages of a batch back to back, each as
+/// `protocol tag | envelope length (u32 LE) | envelope | payload length (u32 LE) | payload`.
+pub(super) fn encode_entries(messages: &[Message]) -> Result<Vec<u8>, CodecError> {
+    let mut raw = Vec::new();
+    for message in messages {
+        let envelope = to_msgpack_vec(&message.envelope).map_err(|e| CodecError::Serialize(e.to_string()))?;
+        let payload = message.serialize_payload().map_err(|e| CodecError::Serialize(e.to_string()))?;
+
+        raw.put_u8(protocol_tag(&message.payload));
+        raw.put_u32_le(frame_len(envelope.len())?);
+        raw.extend_from_slice(&envelope);
+        raw.put_u32_le(frame_len(payload.len())?);
+        raw.extend_from_slice(&payload);
+    }
+    Ok(raw)
+}
+
+pub(super) fn decode_entries(mut raw: &[u8], compression: &Compression) -> Result<Vec<Message>, CodecError> {
+    let mut messages = Vec::new();
+    while raw.has_remaining() {
+        let protocol = protocol_from_tag(raw.get_u8())?;
+        let envelope = take(&mut raw)?;
+        let envelope: Envelope = from_msgpack_slice(envelope).map_err(|e| CodecError::Deserialize(e.to_string()))?;
+        let payload = take(&mut raw)?;
+        let payload = Message::deserialize_payload(payload, &protocol).map_err(|e| CodecError::Deserialize(e.to_string()))?;
+
+        messages.push(Message { compression: compression.clone(), payload: protocol.with_value(payload), envelope });
+    }
+    Ok(messages)
+}
+
+/// Splits a length-prefixed section off the front of `raw`.
+fn take<'a>(raw: &mut &'a [u8]) -> Result<&'a [u8], CodecError> {
+    if raw.remaining() < 4 {
+        return Err(CodecError::CorruptFrame("truncated batch entry".to_string()));
+    }
+    let len = raw.get_u32_le() as usize;
+    if raw.remaining() < len {
+        return Err(CodecError::CorruptFrame(format!("batch entry of {} bytes overruns the frame", len)));
+    }
+    let (section, rest) = raw.split_at(len);
+    *raw = rest;
+    Ok(section)
+}
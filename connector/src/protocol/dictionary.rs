@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use serde_json::from_str as from_json_str;
+use tokio::io;
+use zstd::zstd_safe;
+
+use super::Message;
+
+/// Default upper bound for the size of a trained dictionary, matching the
+/// `zstd --train` default.
+pub const DEFAULT_DICTIONARY_SIZE: usize = 110 * 1024;
+
+/// A zstd dictionary, identified by the dictionary ID zstd stores in its
+/// header. Compressed frames record that ID, so the receiving side can pick
+/// the matching dictionary.
+pub struct ZstdDictionary {
+    id: u32,
+    data: Vec<u8>,
+}
+
+impl ZstdDictionary {
+    pub fn new(data: Vec<u8>) -> Result<Self, io::Error> {
+        let id = zstd_safe::get_dict_id_from_dict(&data);
+        if id == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a zstd dictionary, or dictionary without an ID"));
+        }
+        Ok(ZstdDictionary { id, data })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, io::Error> {
+        Self::new(fs::read(path)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), io::Error> {
+        fs::write(path, &self.data)
+    }
+
+    /// Trains a dictionary from the encoded payloads of `samples`.
+    pub fn train(samples: &[Message], max_size: usize) -> Result<Self, io::Error> {
+        let payloads = samples.iter()
+            .map(|message| message.serialize_payload())
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::new(zstd::dict::from_samples(&payloads, max_size)?)
+    }
+
+    /// Trains a dictionary from a recorded file holding one JSON-serialized
+    /// `Message` per line.
+    pub fn train_from_file<P: AsRef<Path>>(path: P, max_size: usize) -> Result<Self, io::Error> {
+        let samples = fs::read_to_string(path)?
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| from_json_str::<Message>(line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)))
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::train(&samples, max_size)
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl fmt::Debug for ZstdDictionary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ZstdDictionary").field("id", &self.id).field("size", &self.data.len()).finish()
+    }
+}
+
+/// The dictionaries both ends of a connection have agreed on, by ID.
+#[derive(Debug, Clone, Default)]
+pub struct ZstdDictionaries {
+    by_id: HashMap<u32, Arc<ZstdDictionary>>,
+}
+
+impl ZstdDictionaries {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load<P: AsRef<Path>>(paths: &[P]) -> Result<Self, io::Error> {
+        let mut dictionaries = Self::new();
+        for path in paths {
+            dictionaries.insert(ZstdDictionary::load(path)?);
+        }
+        Ok(dictionaries)
+    }
+
+    pub fn insert(&mut self, dictionary: ZstdDictionary) {
+        self.by_id.insert(dictionary.id, Arc::new(dictionary));
+    }
+
+    pub fn get(&self, id: u32) -> Option<&ZstdDictionary> {
+        self.by_id.get(&id).map(Arc::as_ref)
+    }
+
+    /// Looks up `id`, failing with `NotFound` for dictionaries that were
+    /// never loaded.
+    pub(crate) fn require(&self, id: u32) -> Result<&ZstdDictionary, io::Error> {
+        self.get(id).ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("Unknown zstd dictionary {}", id)))
+    }
+}
+
+/// Returns the dictionary ID recorded in a zstd frame, if any.
+pub(crate) fn frame_dictionary_id(data: &[u8]) -> Option<u32> {
+    match zstd_safe::get_dict_id_from_frame(data) {
+        0 => None,
+        id => Some(id),
+    }
+}
@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+use std::time::SystemTime;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Metadata travelling with every `Message`, independent of its payload.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Envelope {
+    /// Unique per message; kept when the message is resent.
+    pub id: Uuid,
+    pub created_at: SystemTime,
+    /// Name of the data source that produced the message.
+    #[serde(default)]
+    pub source: Option<String>,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// Number of times the message has been sent again after a failure.
+    #[serde(default)]
+    pub redeliveries: u32,
+}
+
+impl Envelope {
+    pub fn new() -> Self {
+        Envelope {
+            id: Uuid::new_v4(),
+            created_at: SystemTime::now(),
+            source: None,
+            headers: HashMap::new(),
+            redeliveries: 0,
+        }
+    }
+
+    pub fn with_source(mut self, source: &str) -> Self {
+        self.source = Some(source.to_string());
+        self
+    }
+
+    pub fn with_header(mut self, key: &str, value: &str) -> Self {
+        self.headers.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// A fresh envelope (new id and timestamp) for a message produced in
+    /// response to this one, carrying over its source and headers.
+    pub fn derive(&self) -> Self {
+        Envelope {
+            source: self.source.clone(),
+            headers: self.headers.clone(),
+            ..Envelope::new()
+        }
+    }
+}
+
+impl Default for Envelope {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,48 @@
+use thiserror::Error;
+use tokio::io;
+
+#[derive(Debug, Error)]
+pub enum CodecError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Unsupported frame version {0}")]
+    UnsupportedVersion(u8),
+    #[error("Unknown protocol tag {0}")]
+    UnknownProtocol(u8),
+    #[error("Unknown frame kind {0}")]
+    UnknownFrameKind(u8),
+    #[error("Unexpected {0} frame")]
+    UnexpectedFrame(&'static str),
+    #[error("Unknown compression tag {0}")]
+    UnknownCompression(u8),
+    #[error("Frame of {size} bytes exceeds the maximum of {max} bytes")]
+    FrameTooLarge { size: u64, max: usize },
+    #[error("Corrupt frame: {0}")]
+    CorruptFrame(String),
+    #[error("Unknown zstd dictionary {0}")]
+    UnknownDictionary(u32),
+    #[error("Failed to compress payload: {0}")]
+    Compression(String),
+    #[error("Failed to decompress payload: {0}")]
+    Decompression(String),
+    #[error("Failed to serialize payload: {0}")]
+    Serialize(String),
+    #[error("Failed to deserialize payload: {0}")]
+    Deserialize(String),
+}
+
+impl CodecError {
+    pub fn is_broken_pipe(&self) -> bool {
+        matches!(self, CodecError::Io(e) if e.kind() == io::ErrorKind::BrokenPipe)
+    }
+
+    /// Whether the item being encoded can never be sent, however often it
+    /// is retried, as opposed to the connection failing.
+    pub fn is_unencodable(&self) -> bool {
+        matches!(self,
+            CodecError::FrameTooLarge { .. }
+            | CodecError::Serialize(_)
+            | CodecError::Compression(_)
+            | CodecError::UnknownDictionary(_))
+    }
+}
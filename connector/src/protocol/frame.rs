@@ -0,0 +1,172 @@
+use serde_json::Value as JsonValue;
+use bytes::{BytesMut, BufMut, Buf};
+
+use super::{CodecError, Compression, Protocol};
+
+/// Marks the start of every frame. Legacy frames start with a little-endian
+/// `u64` metadata length instead, which never matches this value in practice.
+pub const FRAME_MAGIC: [u8; 4] = *b"MFLW";
+
+/// Bumped whenever the header layout changes; frames carrying any other
+/// version are rejected.
+pub const FRAME_VERSION: u8 = 3;
+
+/// Size of the fixed header that precedes every frame. The MessagePack
+/// encoded `Envelope` follows it, then the body:
+///
+/// | offset | size | field                              |
+/// |--------|------|------------------------------------|
+/// | 0      | 4    | magic (`MFLW`)                     |
+/// | 4      | 1    | version                            |
+/// | 5      | 1    | protocol tag                       |
+/// | 6      | 1    | compression tag                    |
+/// | 7      | 4    | compression level (u32 LE)         |
+/// | 11     | 4    | uncompressed body length (u32 LE)  |
+/// | 15     | 4    | body length on the wire (u32 LE)   |
+/// | 19     | 4    | envelope length (u32 LE)           |
+/// | 23     | 1    | frame kind                         |
+pub const HEADER_LEN: usize = 24;
+
+const KIND_MESSAGE: u8 = 0;
+const KIND_BATCH: u8 = 1;
+const KIND_ACK: u8 = 2;
+const KIND_NACK: u8 = 3;
+const KIND_CHALLENGE: u8 = 4;
+const KIND_AUTH: u8 = 5;
+const KIND_PING: u8 = 6;
+const KIND_PONG: u8 = 7;
+
+const PROTOCOL_JSON: u8 = 0;
+const PROTOCOL_MSGPACK: u8 = 1;
+
+const COMPRESSION_NONE: u8 = 0;
+const COMPRESSION_ZSTD: u8 = 1;
+const COMPRESSION_LZ4: u8 = 2;
+const COMPRESSION_GZIP: u8 = 3;
+const COMPRESSION_SNAPPY: u8 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+    /// A single message; the envelope section holds its envelope.
+    Message,
+    /// Several messages compressed together; the envelope section is empty
+    /// and each entry of the body carries its own envelope.
+    Batch,
+    /// Acknowledges a message; the body is its 16 byte id.
+    Ack,
+    /// Rejects a message; the body is its 16 byte id followed by the UTF-8
+    /// reason.
+    Nack,
+    /// Sent by a server requiring authentication; the body is a nonce.
+    Challenge,
+    /// A client's answer to a challenge; the body is its proof.
+    Auth,
+    /// Heartbeat, answered with a `Pong`; the body is empty.
+    Ping,
+    Pong,
+}
+
+#[derive(Debug, Clone)]
+pub struct FrameHeader {
+    pub kind: FrameKind,
+    /// Protocol of the body; the wrapped value is a placeholder.
+    pub protocol: Protocol,
+    pub compression: Compression,
+    pub raw_len: u32,
+    pub body_len: u32,
+    pub envelope_len: u32,
+}
+
+impl FrameHeader {
+    pub fn encode(&self, dst: &mut BytesMut) {
+        let (compression_tag, level) = match self.compression {
+            Compression::None => (COMPRESSION_NONE, 0),
+            Compression::Zstd(level) | Compression::ZstdDict { level, .. } => (COMPRESSION_ZSTD, level),
+            Compression::Lz4 => (COMPRESSION_LZ4, 0),
+            Compression::Gzip(level) => (COMPRESSION_GZIP, level),
+            Compression::Snappy => (COMPRESSION_SNAPPY, 0),
+        };
+        let kind_tag = match self.kind {
+            FrameKind::Message => KIND_MESSAGE,
+            FrameKind::Batch => KIND_BATCH,
+            FrameKind::Ack => KIND_ACK,
+            FrameKind::Nack => KIND_NACK,
+            FrameKind::Challenge => KIND_CHALLENGE,
+            FrameKind::Auth => KIND_AUTH,
+            FrameKind::Ping => KIND_PING,
+            FrameKind::Pong => KIND_PONG,
+        };
+
+        dst.extend_from_slice(&FRAME_MAGIC);
+        dst.put_u8(FRAME_VERSION);
+        dst.put_u8(protocol_tag(&self.protocol));
+        dst.put_u8(compression_tag);
+        dst.put_u32_le(level);
+        dst.put_u32_le(self.raw_len);
+        dst.put_u32_le(self.body_len);
+        dst.put_u32_le(self.envelope_len);
+        dst.put_u8(kind_tag);
+    }
+
+    /// Parses the header at the start of `src` without consuming it.
+    pub fn decode(src: &[u8]) -> Result<FrameHeader, CodecError> {
+        let mut buf = &src[FRAME_MAGIC.len()..HEADER_LEN];
+        let version = buf.get_u8();
+        if version != FRAME_VERSION {
+            return Err(CodecError::UnsupportedVersion(version));
+        }
+        let protocol = protocol_from_tag(buf.get_u8())?;
+        let compression_tag = buf.get_u8();
+        let level = buf.get_u32_le();
+        let compression = match compression_tag {
+            COMPRESSION_NONE => Compression::None,
+            // A dictionary, if any, is recorded in the zstd frame itself and
+            // resolved once the body is available.
+            COMPRESSION_ZSTD => Compression::Zstd(level),
+            COMPRESSION_LZ4 => Compression::Lz4,
+            COMPRESSION_GZIP => Compression::Gzip(level),
+            COMPRESSION_SNAPPY => Compression::Snappy,
+            tag => return Err(CodecError::UnknownCompression(tag)),
+        };
+
+        let raw_len = buf.get_u32_le();
+        let body_len = buf.get_u32_le();
+        let envelope_len = buf.get_u32_le();
+        let kind = match buf.get_u8() {
+            KIND_MESSAGE => FrameKind::Message,
+            KIND_BATCH => FrameKind::Batch,
+            KIND_ACK => FrameKind::Ack,
+            KIND_NACK => FrameKind::Nack,
+            KIND_CHALLENGE => FrameKind::Challenge,
+            KIND_AUTH => FrameKind::Auth,
+            KIND_PING => FrameKind::Ping,
+            KIND_PONG => FrameKind::Pong,
+            tag => return Err(CodecError::UnknownFrameKind(tag)),
+        };
+
+        Ok(FrameHeader {
+            kind,
+            protocol,
+            compression,
+            raw_len,
+            body_len,
+            envelope_len,
+        })
+    }
+}
+
+pub fn protocol_tag(protocol: &Protocol) -> u8 {
+    match protocol {
+        Protocol::Json(_) => PROTOCOL_JSON,
+        Protocol::MsgPack(_) => PROTOCOL_MSGPACK,
+    }
+}
+
+/// Maps a protocol tag to its variant; the wrapped value is a placeholder.
+pub fn protocol_from_tag(tag: u8) -> Result<Protocol, CodecError> {
+    match tag {
+        PROTOCOL_JSON => Ok(Protocol::Json(JsonValue::Null)),
+        PROTOCOL_MSGPACK => Ok(Protocol::MsgPack(JsonValue::Null)),
+        tag => Err(CodecError::UnknownProtocol(tag)),
+    }
+}
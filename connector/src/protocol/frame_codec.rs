@@ -0,0 +1,61 @@
+use bytes::BytesMut;
+use tokio_util::codec::{Decoder, Encoder};
+use uuid::Uuid;
+
+use super::{CodecError, Message, MessageBatch, MessageCodec};
+
+/// Anything that can travel over a connection: messages one way, their
+/// acknowledgements the other.
+#[derive(Debug, Clone)]
+pub enum Frame {
+    Message(Message),
+    /// The message with this id was handled and need not be sent again.
+    Ack(Uuid),
+    /// The message with this id could not be handled; the sender may retry.
+    Nack { id: Uuid, reason: String },
+    /// Nonce the server asks a new connection to authenticate against.
+    Challenge(Vec<u8>),
+    /// The client's proof of knowing the shared secret.
+    Auth(Vec<u8>),
+    /// Heartbeat probing whether the peer is still there.
+    Ping,
+    Pong,
+}
+
+/// Like `MessageCodec`, but also reads and writes control frames such as
+/// ACKs and NACKs.
+#[derive(Debug, Clone, Default)]
+pub struct FrameCodec {
+    codec: MessageCodec,
+}
+
+impl From<MessageCodec> for FrameCodec {
+    fn from(codec: MessageCodec) -> Self {
+        FrameCodec { codec }
+    }
+}
+
+impl Encoder<Frame> for FrameCodec {
+    type Error = CodecError;
+
+    fn encode(&mut self, item: Frame, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.codec.encode_frame_item(item, dst)
+    }
+}
+
+impl Encoder<MessageBatch> for FrameCodec {
+    type Error = CodecError;
+
+    fn encode(&mut self, item: MessageBatch, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.codec.encode(item, dst)
+    }
+}
+
+impl Decoder for FrameCodec {
+    type Item = Frame;
+    type Error = CodecError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Frame>, Self::Error> {
+        self.codec.decode_frame(src)
+    }
+}
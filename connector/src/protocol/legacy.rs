@@ -0,0 +1,78 @@
+use serde_json::{from_slice as from_json_slice, to_vec as to_json_vec};
+use bytes::{BytesMut, BufMut, Buf};
+use tokio_util::codec::{Decoder, Encoder};
+
+use super::{CodecError, Message, ZstdDictionaries, DEFAULT_MAX_FRAME_SIZE};
+
+/// The original frame format: a length-prefixed JSON copy of the whole
+/// `Message`, followed by a length-prefixed copy of the encoded payload.
+///
+/// Kept so that `MessageCodec` can still read frames from peers that have not
+/// been upgraded yet, and as a baseline for the codec benchmarks.
+pub struct LegacyMessageCodec;
+
+impl Encoder<Message> for LegacyMessageCodec {
+    type Error = CodecError;
+
+    fn encode(&mut self, item: Message, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let payload = item.encode_data()?;
+        let metadata = to_json_vec(&item).map_err(|e| CodecError::Serialize(e.to_string()))?;
+
+        dst.reserve(16 + metadata.len() + payload.len());
+        dst.put_u64_le(metadata.len() as u64);
+        dst.extend_from_slice(&metadata);
+        dst.put_u64_le(payload.len() as u64);
+        dst.extend_from_slice(&payload);
+        Ok(())
+    }
+}
+
+impl Decoder for LegacyMessageCodec {
+    type Item = Message;
+    type Error = CodecError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Message>, Self::Error> {
+        decode(src, DEFAULT_MAX_FRAME_SIZE)
+    }
+}
+
+/// Decodes one legacy frame, rejecting any section larger than
+/// `max_frame_size` before buffering or decompressing it.
+pub(super) fn decode(src: &mut BytesMut, max_frame_size: usize) -> Result<Option<Message>, CodecError> {
+    let check = |size: u64| {
+        if size > max_frame_size as u64 {
+            Err(CodecError::FrameTooLarge { size, max: max_frame_size })
+        } else {
+            Ok(size as usize)
+        }
+    };
+
+    if src.len() < 8 {
+        return Ok(None);
+    }
+
+    let metadata_len = check((&src[..8]).get_u64_le())?;
+
+    if src.len() < 8 + metadata_len + 8 {
+        return Ok(None);
+    }
+
+    let payload_len = check((&src[8 + metadata_len..16 + metadata_len]).get_u64_le())?;
+
+    if src.len() < 16 + metadata_len + payload_len {
+        return Ok(None);
+    }
+
+    src.advance(8);
+    let metadata_buf = src.split_to(metadata_len);
+    let message: Message = from_json_slice(&metadata_buf).map_err(|e| CodecError::Deserialize(e.to_string()))?;
+
+    src.advance(8);
+    let payload_buf = src.split_to(payload_len);
+
+    let raw = Message::decompress_bounded(&payload_buf, &message.compression, max_frame_size, &ZstdDictionaries::default())
+        .map_err(|e| CodecError::Decompression(e.to_string()))?;
+    let payload = Message::deserialize_payload(&raw, &message.payload).map_err(|e| CodecError::Deserialize(e.to_string()))?;
+
+    Ok(Some(Message { compression: message.compression, payload: message.payload.with_value(payload), envelope: message.envelope }))
+}
@@ -1,66 +1,232 @@
 use serde::{Deserialize, Serialize};
 use serde_json::{Value as JsonValue, from_slice as from_json_slice, to_vec as to_json_vec};
-use zstd::stream::{encode_all as zstd_encode_all, decode_all as zstd_decode_all};
+use rmp_serde::{from_slice as from_msgpack_slice, to_vec as to_msgpack_vec};
+use zstd::bulk::{self as zstd_bulk, Compressor as ZstdCompressor, Decompressor as ZstdDecompressor};
+use zstd::stream::read::Decoder as ZstdDecoder;
+use lz4_flex::frame::{FrameDecoder as Lz4Decoder, FrameEncoder as Lz4Encoder};
+use flate2::{Compression as GzLevel, read::GzDecoder, write::GzEncoder};
+use snap::{read::FrameDecoder as SnappyDecoder, write::FrameEncoder as SnappyEncoder};
+use std::io::{Read, Write};
 use tokio::io;
 use std::time::Instant;
 
+use super::{CompressionPolicy, Envelope, ZstdDictionaries, DEFAULT_MAX_FRAME_SIZE};
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum Protocol {
     Json(JsonValue),
+    MsgPack(JsonValue),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl Protocol {
+    pub fn value(&self) -> &JsonValue {
+        match self {
+            Protocol::Json(value) | Protocol::MsgPack(value) => value,
+        }
+    }
+
+    pub fn into_value(self) -> JsonValue {
+        match self {
+            Protocol::Json(value) | Protocol::MsgPack(value) => value,
+        }
+    }
+
+    /// Wraps `value` in the same protocol variant as `self`.
+    pub fn with_value(&self, value: JsonValue) -> Protocol {
+        match self {
+            Protocol::Json(_) => Protocol::Json(value),
+            Protocol::MsgPack(_) => Protocol::MsgPack(value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Compression {
     None,
     Zstd(u32),
+    /// Zstd with a shared dictionary, referenced by its ID. Both ends must
+    /// have loaded the dictionary (see `ZstdDictionaries`).
+    ZstdDict { level: u32, dictionary: u32 },
+    Lz4,
+    Gzip(u32),
+    Snappy,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Message {
     pub compression: Compression,
     pub payload: Protocol,
+    #[serde(default)]
+    pub envelope: Envelope,
 }
 
 impl Message {
+    /// A message with a fresh envelope.
+    pub fn new(compression: Compression, payload: Protocol) -> Self {
+        Message { compression, payload, envelope: Envelope::new() }
+    }
+
+    /// Encodes the payload with the message's compression. No dictionaries
+    /// are available here: `Compression::ZstdDict` fails with
+    /// `ErrorKind::InvalidInput`, and needs `MessageCodec::encode_data`.
     pub fn encode_data(&self) -> Result<Vec<u8>, io::Error> {
-        let data = match &self.payload {
-            Protocol::Json(value) => to_json_vec(value),
-        }.map_err(|e| {
-            println!("Failed to serialize data: {:?}", e);
-            io::Error::new(io::ErrorKind::Other, e)
-        })?;
-
-        let compressed_data = match self.compression {
-            Compression::None => data,
-            Compression::Zstd(level) => {
-                let start = Instant::now();
-                let result = zstd_encode_all(&data[..], level as i32).unwrap();
-                let duration = start.elapsed();
-                println!("Compression time: {:?}", duration);
-                result
-            }
-        };
+        if let Compression::ZstdDict { dictionary, .. } = self.compression {
+            return Err(dictionary_required(dictionary));
+        }
+        self.encode_data_with_dictionaries(&ZstdDictionaries::default())
+    }
 
-        Ok(compressed_data)
+    pub(crate) fn encode_data_with_dictionaries(&self, dictionaries: &ZstdDictionaries) -> Result<Vec<u8>, io::Error> {
+        let data = self.serialize_payload()?;
+        match self.compression {
+            Compression::None => Ok(data),
+            _ => Self::compress(&data, &self.compression, dictionaries),
+        }
+    }
+
+    /// Encodes the payload, letting `policy` decide whether compressing it
+    /// is worthwhile. Returns the compression that was actually applied
+    /// alongside the encoded bytes.
+    pub fn encode_data_with_policy(&self, policy: &CompressionPolicy, dictionaries: &ZstdDictionaries) -> Result<(Compression, Vec<u8>), io::Error> {
+        let data = self.serialize_payload()?;
+        policy.apply(data, &self.compression, dictionaries)
     }
 
+    /// Decodes a payload written by `encode_data`, refusing one that
+    /// decompresses past `DEFAULT_MAX_FRAME_SIZE`. As there, no
+    /// dictionaries are available: `Compression::ZstdDict` fails with
+    /// `ErrorKind::InvalidInput`, and needs `MessageCodec::decode_data`.
     pub fn decode_data(data: &[u8], compression: &Compression, protocol: &Protocol) -> Result<JsonValue, io::Error> {
-        let decompressed_data = match compression {
-            Compression::None => data.to_vec(),
-            Compression::Zstd(_) => {
+        Self::decode_data_with_limit(data, compression, protocol, DEFAULT_MAX_FRAME_SIZE)
+    }
+
+    /// Like `decode_data`, refusing payloads that decompress past
+    /// `max_size` bytes instead.
+    pub fn decode_data_with_limit(data: &[u8], compression: &Compression, protocol: &Protocol, max_size: usize) -> Result<JsonValue, io::Error> {
+        if let Compression::ZstdDict { dictionary, .. } = compression {
+            return Err(dictionary_required(*dictionary));
+        }
+        let decompressed_data = Self::decompress_bounded(data, compression, max_size, &ZstdDictionaries::default())?;
+        Self::deserialize_payload(&decompressed_data, protocol)
+    }
+
+    /// Approximate size of the serialized payload, worked out without
+    /// serializing it.
+    pub(crate) fn estimated_size(&self) -> usize {
+        estimated_size(self.payload.value())
+    }
+
+    pub(crate) fn serialize_payload(&self) -> Result<Vec<u8>, io::Error> {
+        match &self.payload {
+            Protocol::Json(value) => to_json_vec(value).map_err(|e| {
+                println!("Failed to serialize data: {:?}", e);
+                io::Error::other(e)
+            }),
+            Protocol::MsgPack(value) => to_msgpack_vec(value).map_err(|e| {
+                println!("Failed to serialize data: {:?}", e);
+                io::Error::other(e)
+            }),
+        }
+    }
+
+    pub(crate) fn deserialize_payload(data: &[u8], protocol: &Protocol) -> Result<JsonValue, io::Error> {
+        match protocol {
+            Protocol::Json(_) => from_json_slice(data).map_err(|e| {
+                println!("Failed to deserialize JSON: {:?}", e);
+                io::Error::other(e)
+            }),
+            Protocol::MsgPack(_) => from_msgpack_slice(data).map_err(|e| {
+                println!("Failed to deserialize MessagePack: {:?}", e);
+                io::Error::other(e)
+            }),
+        }
+    }
+
+    pub(crate) fn compress(data: &[u8], compression: &Compression, dictionaries: &ZstdDictionaries) -> Result<Vec<u8>, io::Error> {
+        let start = Instant::now();
+        let result = match compression {
+            Compression::None => return Ok(data.to_vec()),
+            Compression::Zstd(level) => zstd_bulk::compress(data, *level as i32)?,
+            Compression::ZstdDict { level, dictionary } => {
+                let dictionary = dictionaries.require(*dictionary)?;
+                ZstdCompressor::with_dictionary(*level as i32, dictionary.data())?.compress(data)?
+            }
+            Compression::Lz4 => {
+                let mut encoder = Lz4Encoder::new(Vec::with_capacity(data.len()));
+                encoder.write_all(data)?;
+                encoder.finish().map_err(io::Error::other)?
+            }
+            Compression::Gzip(level) => {
+                let mut encoder = GzEncoder::new(Vec::with_capacity(data.len()), GzLevel::new((*level).min(9)));
+                encoder.write_all(data)?;
+                encoder.finish()?
+            }
+            Compression::Snappy => {
+                let mut encoder = SnappyEncoder::new(Vec::with_capacity(data.len()));
+                encoder.write_all(data)?;
+                encoder.into_inner().map_err(|e| io::Error::other(e.to_string()))?
+            }
+        };
+        let duration = start.elapsed();
+        println!("Compression time: {:?}", duration);
+        Ok(result)
+    }
+
+    /// Decompresses a payload whose decompressed size (`raw_len`) is known
+    /// up front, allocating the output buffer once.
+    pub(crate) fn decompress(data: &[u8], compression: &Compression, raw_len: usize, dictionaries: &ZstdDictionaries) -> Result<Vec<u8>, io::Error> {
+        match compression {
+            Compression::None => Ok(data.to_vec()),
+            Compression::Zstd(_) | Compression::ZstdDict { .. } => {
                 let start = Instant::now();
-                let result = zstd_decode_all(data).unwrap();
+                let result = match compression {
+                    Compression::ZstdDict { dictionary, .. } => {
+                        ZstdDecompressor::with_dictionary(dictionaries.require(*dictionary)?.data())?.decompress(data, raw_len)?
+                    }
+                    _ => zstd_bulk::decompress(data, raw_len)?,
+                };
                 let duration = start.elapsed();
                 println!("Decompression time: {:?}", duration);
-                result
+                Ok(result)
             }
+            _ => Self::decompress_bounded(data, compression, raw_len, dictionaries),
+        }
+    }
+
+    /// Decompresses a payload of unknown size, failing once the output
+    /// grows past `limit` bytes.
+    pub(crate) fn decompress_bounded(data: &[u8], compression: &Compression, limit: usize, dictionaries: &ZstdDictionaries) -> Result<Vec<u8>, io::Error> {
+        let start = Instant::now();
+        let decoder: Box<dyn Read + '_> = match compression {
+            Compression::None => return Ok(data.to_vec()),
+            Compression::Zstd(_) => Box::new(ZstdDecoder::new(data)?),
+            Compression::ZstdDict { dictionary, .. } => Box::new(ZstdDecoder::with_dictionary(data, dictionaries.require(*dictionary)?.data())?),
+            Compression::Lz4 => Box::new(Lz4Decoder::new(data)),
+            Compression::Gzip(_) => Box::new(GzDecoder::new(data)),
+            Compression::Snappy => Box::new(SnappyDecoder::new(data)),
         };
 
-        match protocol {
-            Protocol::Json(_) => from_json_slice(&decompressed_data).map_err(|e| {
-                println!("Failed to deserialize JSON: {:?}", e);
-                io::Error::new(io::ErrorKind::Other, e)
-            }),
+        let mut result = Vec::new();
+        decoder.take((limit as u64).saturating_add(1)).read_to_end(&mut result)?;
+        if result.len() > limit {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Decompressed payload exceeds {} bytes", limit)));
         }
+        let duration = start.elapsed();
+        println!("Decompression time: {:?}", duration);
+        Ok(result)
     }
-}
\ No newline at end of file
+}
+
+fn dictionary_required(dictionary: u32) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, format!("zstd dictionary {} required; use a MessageCodec holding it", dictionary))
+}
+
+fn estimated_size(value: &JsonValue) -> usize {
+    match value {
+        JsonValue::Null | JsonValue::Bool(_) => 5,
+        JsonValue::Number(_) => 9,
+        JsonValue::String(s) => s.len() + 2,
+        JsonValue::Array(items) => 2 + items.iter().map(|item| estimated_size(item) + 1).sum::<usize>(),
+        JsonValue::Object(fields) => 2 + fields.iter().map(|(key, value)| key.len() + 4 + estimated_size(value)).sum::<usize>(),
+    }
+}
@@ -1,78 +1,307 @@
-use serde_json::{from_slice as from_json_slice, to_vec as to_json_vec};
-use tokio::io;
-use bytes::{BytesMut, BufMut, Buf};
+use std::collections::VecDeque;
+use bytes::{BytesMut, Buf};
 use tokio_util::codec::{Decoder, Encoder};
-use std::time::Instant;
+use rmp_serde::{from_slice as from_msgpack_slice, to_vec as to_msgpack_vec};
+use serde_json::Value as JsonValue;
+use tokio::io;
+use uuid::Uuid;
 
 mod message;
 pub use message::{Message, Protocol, Compression};
 
-pub struct MessageCodec;
+mod envelope;
+pub use envelope::Envelope;
 
-impl Encoder<Message> for MessageCodec {
-    type Error = io::Error;
+mod policy;
+pub use policy::CompressionPolicy;
 
-    fn encode(&mut self, item: Message, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        let start = Instant::now();
-        let payload = item.encode_data()?;
+mod dictionary;
+pub use dictionary::{ZstdDictionary, ZstdDictionaries, DEFAULT_DICTIONARY_SIZE};
+
+mod error;
+pub use error::CodecError;
+
+mod frame;
+use frame::{FrameHeader, FrameKind, FRAME_MAGIC, HEADER_LEN};
 
-        let metadata = to_json_vec(&item).unwrap();
-        let metadata_len = metadata.len() as u64;
-        let payload_len = payload.len() as u64;
+mod batch;
+pub use batch::MessageBatch;
+
+mod frame_codec;
+pub use frame_codec::{Frame, FrameCodec};
+
+mod legacy;
+pub use legacy::LegacyMessageCodec;
+
+/// Largest frame body, compressed or not, accepted by default.
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+/// Frames each `Message` as a fixed `HEADER_LEN` header followed by its
+/// envelope and the encoded (and possibly compressed) payload.
+///
+/// A `MessageBatch` is sent as a single frame; when decoding, its messages
+/// are handed out one at a time, as if they had been sent separately.
+///
+/// Frames in the legacy format are still accepted when decoding so that
+/// peers can be upgraded one at a time.
+#[derive(Debug, Clone)]
+pub struct MessageCodec {
+    max_frame_size: usize,
+    compression_policy: CompressionPolicy,
+    dictionaries: ZstdDictionaries,
+    /// Messages of a decoded batch not yet returned by `decode`.
+    pending: VecDeque<Message>,
+}
 
-        dst.put_u64_le(metadata_len);
-        dst.extend_from_slice(&metadata);
-        dst.put_u64_le(payload_len);
-        dst.extend_from_slice(&payload);
+impl MessageCodec {
+    pub fn new() -> Self {
+        MessageCodec {
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            compression_policy: CompressionPolicy::default(),
+            dictionaries: ZstdDictionaries::default(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.max_frame_size = max_frame_size;
+        self
+    }
+
+    pub fn with_compression_policy(mut self, compression_policy: CompressionPolicy) -> Self {
+        self.compression_policy = compression_policy;
+        self
+    }
+
+    /// Dictionaries available for `Compression::ZstdDict` on encode, and for
+    /// zstd frames that reference a dictionary on decode.
+    pub fn with_dictionaries(mut self, dictionaries: ZstdDictionaries) -> Self {
+        self.dictionaries = dictionaries;
+        self
+    }
+
+    pub fn max_frame_size(&self) -> usize {
+        self.max_frame_size
+    }
+
+    /// Like `Message::encode_data`, with the codec's dictionaries available
+    /// to `Compression::ZstdDict`.
+    pub fn encode_data(&self, message: &Message) -> Result<Vec<u8>, io::Error> {
+        message.encode_data_with_dictionaries(&self.dictionaries)
+    }
+
+    /// Like `Message::decode_data`, with the codec's dictionaries available
+    /// to `Compression::ZstdDict` and its maximum frame size as the bound.
+    pub fn decode_data(&self, data: &[u8], compression: &Compression, protocol: &Protocol) -> Result<JsonValue, io::Error> {
+        let raw = Message::decompress_bounded(data, compression, self.max_frame_size, &self.dictionaries)?;
+        Message::deserialize_payload(&raw, protocol)
+    }
+
+    fn check_frame_size(&self, size: u64) -> Result<usize, CodecError> {
+        if size > self.max_frame_size as u64 {
+            return Err(CodecError::FrameTooLarge { size, max: self.max_frame_size });
+        }
+        Ok(size as usize)
+    }
+
+    /// Compresses `raw` according to the policy and writes the whole frame.
+    fn encode_frame(&self, kind: FrameKind, protocol: Protocol, compression: &Compression, raw: Vec<u8>, envelope: &[u8], dst: &mut BytesMut) -> Result<(), CodecError> {
+        let raw_len = self.check_frame_size(raw.len() as u64)?;
+        if let Compression::ZstdDict { dictionary, .. } = compression {
+            if self.dictionaries.get(*dictionary).is_none() {
+                return Err(CodecError::UnknownDictionary(*dictionary));
+            }
+        }
+        let (compression, body) = self.compression_policy.apply(raw, compression, &self.dictionaries)
+            .map_err(|e| CodecError::Compression(e.to_string()))?;
+        let body_len = self.check_frame_size(body.len() as u64)?;
+        let envelope_len = self.check_frame_size(envelope.len() as u64)?;
+
+        let header = FrameHeader {
+            kind,
+            protocol,
+            compression,
+            raw_len: frame_len(raw_len)?,
+            body_len: frame_len(body_len)?,
+            envelope_len: frame_len(envelope_len)?,
+        };
+
+        dst.reserve(HEADER_LEN + envelope.len() + body.len());
+        header.encode(dst);
+        dst.extend_from_slice(envelope);
+        dst.extend_from_slice(&body);
 
-        let duration = start.elapsed();
-        println!("Packed message: length {} bytes, time taken: {:?}", metadata_len + payload_len, duration);
         Ok(())
     }
-}
 
-impl Decoder for MessageCodec {
-    type Item = Message;
-    type Error = io::Error;
+    /// Writes an uncompressed frame carrying no message, such as an ACK.
+    fn encode_control(&self, kind: FrameKind, body: &[u8], dst: &mut BytesMut) -> Result<(), CodecError> {
+        let len = frame_len(self.check_frame_size(body.len() as u64)?)?;
+        let header = FrameHeader {
+            kind,
+            protocol: Protocol::Json(JsonValue::Null),
+            compression: Compression::None,
+            raw_len: len,
+            body_len: len,
+            envelope_len: 0,
+        };
 
-    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Message>, Self::Error> {
-        let start = Instant::now();
+        dst.reserve(HEADER_LEN + body.len());
+        header.encode(dst);
+        dst.extend_from_slice(body);
+
+        Ok(())
+    }
 
-        if src.len() < 16 {
+    fn encode_frame_item(&self, item: Frame, dst: &mut BytesMut) -> Result<(), CodecError> {
+        match item {
+            Frame::Message(message) => {
+                let raw = message.serialize_payload().map_err(|e| CodecError::Serialize(e.to_string()))?;
+                let envelope = to_msgpack_vec(&message.envelope).map_err(|e| CodecError::Serialize(e.to_string()))?;
+                self.encode_frame(FrameKind::Message, message.payload, &message.compression, raw, &envelope, dst)
+            }
+            Frame::Ack(id) => self.encode_control(FrameKind::Ack, id.as_bytes(), dst),
+            Frame::Nack { id, reason } => {
+                let mut body = id.as_bytes().to_vec();
+                body.extend_from_slice(reason.as_bytes());
+                self.encode_control(FrameKind::Nack, &body, dst)
+            }
+            Frame::Challenge(nonce) => self.encode_control(FrameKind::Challenge, &nonce, dst),
+            Frame::Auth(proof) => self.encode_control(FrameKind::Auth, &proof, dst),
+            Frame::Ping => self.encode_control(FrameKind::Ping, &[], dst),
+            Frame::Pong => self.encode_control(FrameKind::Pong, &[], dst),
+        }
+    }
+
+    /// Decodes the next frame of any kind. Messages of a batch are returned
+    /// one at a time.
+    fn decode_frame(&mut self, src: &mut BytesMut) -> Result<Option<Frame>, CodecError> {
+        if let Some(message) = self.pending.pop_front() {
+            return Ok(Some(Frame::Message(message)));
+        }
+
+        if src.len() < FRAME_MAGIC.len() {
             return Ok(None);
         }
 
-        let metadata_len = {
-            let mut length_buf = &src[..8];
-            length_buf.get_u64_le() as usize
-        };
+        if src[..FRAME_MAGIC.len()] != FRAME_MAGIC {
+            return Ok(legacy::decode(src, self.max_frame_size)?.map(Frame::Message));
+        }
 
-        if src.len() < 8 + metadata_len + 8 {
+        if src.len() < HEADER_LEN {
             return Ok(None);
         }
 
-        let payload_len = {
-            let mut length_buf = &src[8 + metadata_len..16 + metadata_len];
-            length_buf.get_u64_le() as usize
-        };
+        let header = FrameHeader::decode(src)?;
+        let raw_len = self.check_frame_size(header.raw_len as u64)?;
+        let body_len = self.check_frame_size(header.body_len as u64)?;
+        let envelope_len = self.check_frame_size(header.envelope_len as u64)?;
+        let frame_len = HEADER_LEN + envelope_len + body_len;
 
-        if src.len() < 16 + metadata_len + payload_len {
+        if src.len() < frame_len {
+            src.reserve(frame_len - src.len());
             return Ok(None);
         }
 
-        src.advance(8);
-        let metadata_buf = src.split_to(metadata_len);
-        let message: Message = from_json_slice(&metadata_buf).unwrap();
+        src.advance(HEADER_LEN);
+        let envelope = src.split_to(envelope_len);
+        let body = src.split_to(body_len);
+
+        match header.kind {
+            FrameKind::Ack => return Ok(Some(Frame::Ack(control_id(&body)?))),
+            FrameKind::Nack => {
+                let reason = String::from_utf8_lossy(&body[16.min(body.len())..]).into_owned();
+                return Ok(Some(Frame::Nack { id: control_id(&body)?, reason }));
+            }
+            FrameKind::Challenge => return Ok(Some(Frame::Challenge(body.to_vec()))),
+            FrameKind::Auth => return Ok(Some(Frame::Auth(body.to_vec()))),
+            FrameKind::Ping => return Ok(Some(Frame::Ping)),
+            FrameKind::Pong => return Ok(Some(Frame::Pong)),
+            FrameKind::Message | FrameKind::Batch => {}
+        }
+
+        let compression = match (header.compression, dictionary::frame_dictionary_id(&body)) {
+            (Compression::Zstd(level), Some(id)) => {
+                if self.dictionaries.get(id).is_none() {
+                    return Err(CodecError::UnknownDictionary(id));
+                }
+                Compression::ZstdDict { level, dictionary: id }
+            }
+            (compression, _) => compression,
+        };
+
+        let raw = Message::decompress(&body, &compression, raw_len, &self.dictionaries).map_err(|e| CodecError::Decompression(e.to_string()))?;
+        if raw.len() != raw_len {
+            return Err(CodecError::CorruptFrame(format!("payload decompressed to {} bytes, header announced {}", raw.len(), raw_len)));
+        }
+
+        if header.kind == FrameKind::Batch {
+            self.pending.extend(batch::decode_entries(&raw, &compression)?);
+            // Returning nothing would leave the frames already buffered unread
+            return match self.pending.pop_front() {
+                Some(message) => Ok(Some(Frame::Message(message))),
+                None => Err(CodecError::CorruptFrame("empty batch".to_string())),
+            };
+        }
+
+        let envelope: Envelope = from_msgpack_slice(&envelope).map_err(|e| CodecError::Deserialize(e.to_string()))?;
+        let payload = Message::deserialize_payload(&raw, &header.protocol).map_err(|e| CodecError::Deserialize(e.to_string()))?;
+
+        Ok(Some(Frame::Message(Message { compression, payload: header.protocol.with_value(payload), envelope })))
+    }
+}
+
+impl Default for MessageCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Encoder<Message> for MessageCodec {
+    type Error = CodecError;
+
+    fn encode(&mut self, item: Message, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.encode_frame_item(Frame::Message(item), dst)
+    }
+}
+
+impl Encoder<MessageBatch> for MessageCodec {
+    type Error = CodecError;
 
-        src.advance(8);
-        let payload_buf = src.split_to(payload_len);
+    fn encode(&mut self, item: MessageBatch, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let raw = batch::encode_entries(&item.messages)?;
+        let protocol = Protocol::Json(JsonValue::Null);
+        self.encode_frame(FrameKind::Batch, protocol, &item.compression, raw, &[], dst)
+    }
+}
 
-        let payload = Message::decode_data(&payload_buf, &message.compression, &message.payload)?;
+impl Decoder for MessageCodec {
+    type Item = Message;
+    type Error = CodecError;
 
-        let duration = start.elapsed();
-        println!("Unpacked message: length {} bytes, time taken: {:?}", metadata_len + payload_len, duration);
-        Ok(Some(Message { compression: message.compression, payload: match message.payload {
-            Protocol::Json(_) => Protocol::Json(payload),
-        } }))
+    /// Decodes the next message; control frames are not expected here and
+    /// fail with `CodecError::UnexpectedFrame`.
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Message>, Self::Error> {
+        match self.decode_frame(src)? {
+            Some(Frame::Message(message)) => Ok(Some(message)),
+            Some(Frame::Ack(_)) => Err(CodecError::UnexpectedFrame("ACK")),
+            Some(Frame::Nack { .. }) => Err(CodecError::UnexpectedFrame("NACK")),
+            Some(Frame::Challenge(_)) => Err(CodecError::UnexpectedFrame("challenge")),
+            Some(Frame::Auth(_)) => Err(CodecError::UnexpectedFrame("auth")),
+            Some(Frame::Ping) => Err(CodecError::UnexpectedFrame("ping")),
+            Some(Frame::Pong) => Err(CodecError::UnexpectedFrame("pong")),
+            None => Ok(None),
+        }
     }
 }
+
+/// Reads the message id at the start of an ACK or NACK body.
+fn control_id(body: &[u8]) -> Result<Uuid, CodecError> {
+    body.get(..16)
+        .and_then(|id| Uuid::from_slice(id).ok())
+        .ok_or_else(|| CodecError::CorruptFrame(format!("control frame body of {} bytes has no message id", body.len())))
+}
+
+fn frame_len(len: usize) -> Result<u32, CodecError> {
+    u32::try_from(len).map_err(|_| CodecError::FrameTooLarge { size: len as u64, max: u32::MAX as usize })
+}
@@ -0,0 +1,48 @@
+use serde::{Deserialize, Serialize};
+use tokio::io;
+
+use super::{Compression, Message, ZstdDictionaries};
+
+/// Decides, per payload, whether the requested compression is applied.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompressionPolicy {
+    /// Payloads smaller than this many bytes are sent uncompressed.
+    pub min_size: usize,
+    /// Send the payload uncompressed when compressing it does not make it
+    /// smaller.
+    pub skip_if_larger: bool,
+}
+
+impl CompressionPolicy {
+    /// Compresses every payload with the requested compression, regardless
+    /// of size or outcome.
+    pub fn always() -> Self {
+        CompressionPolicy {
+            min_size: 0,
+            skip_if_larger: false,
+        }
+    }
+
+    pub(crate) fn apply(&self, data: Vec<u8>, compression: &Compression, dictionaries: &ZstdDictionaries) -> Result<(Compression, Vec<u8>), io::Error> {
+        if *compression == Compression::None || data.len() < self.min_size {
+            return Ok((Compression::None, data));
+        }
+
+        let compressed = Message::compress(&data, compression, dictionaries)?;
+        if self.skip_if_larger && compressed.len() >= data.len() {
+            return Ok((Compression::None, data));
+        }
+
+        Ok((compression.clone(), compressed))
+    }
+}
+
+impl Default for CompressionPolicy {
+    fn default() -> Self {
+        CompressionPolicy {
+            min_size: 128,
+            skip_if_larger: true,
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+use crate::Frame;
+
+/// Routes the outcome of handling a received message back to the connection
+/// it arrived on, as an ACK or NACK frame.
+#[derive(Debug, Clone, Default)]
+pub struct Acknowledgements {
+    routes: Arc<Mutex<HashMap<Uuid, mpsc::UnboundedSender<Frame>>>>,
+}
+
+impl Acknowledgements {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn register(&self, id: Uuid, connection: mpsc::UnboundedSender<Frame>) {
+        self.routes.lock().unwrap().insert(id, connection);
+    }
+
+    /// Acknowledges the message with this id. Returns `false` if the
+    /// connection it arrived on is gone, in which case the sender will
+    /// resend it.
+    pub fn ack(&self, id: &Uuid) -> bool {
+        self.send(id, Frame::Ack(*id))
+    }
+
+    /// Rejects the message with this id, asking the sender to retry it.
+    pub fn nack(&self, id: &Uuid, reason: &str) -> bool {
+        self.send(id, Frame::Nack { id: *id, reason: reason.to_string() })
+    }
+
+    /// Drops the routes of connections that have been closed.
+    pub(crate) fn prune(&self) {
+        self.routes.lock().unwrap().retain(|_, connection| !connection.is_closed());
+    }
+
+    fn send(&self, id: &Uuid, frame: Frame) -> bool {
+        match self.routes.lock().unwrap().remove(id) {
+            Some(connection) => connection.send(frame).is_ok(),
+            None => false,
+        }
+    }
+}
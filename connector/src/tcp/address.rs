@@ -0,0 +1,98 @@
+use std::fmt;
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;
+use std::path::PathBuf;
+use tokio::io;
+use tokio::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+use super::stream::BoxedStream;
+
+const UNIX_SCHEME: &str = "unix://";
+
+/// Where a `Server` listens or a `Client` connects: `host:port` for TCP, or
+/// `unix:///path/to.sock` for a Unix domain socket. Unix sockets are only
+/// supported on Unix platforms; elsewhere they fail to connect or bind.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Address {
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+impl Address {
+    pub fn parse(addr: &str) -> Self {
+        match addr.strip_prefix(UNIX_SCHEME) {
+            Some(path) => Address::Unix(PathBuf::from(path)),
+            None => Address::Tcp(addr.to_string()),
+        }
+    }
+
+    pub(crate) async fn connect(&self) -> Result<BoxedStream, io::Error> {
+        match self {
+            Address::Tcp(addr) => Ok(Box::new(TcpStream::connect(addr).await?)),
+            #[cfg(unix)]
+            Address::Unix(path) => Ok(Box::new(UnixStream::connect(path).await?)),
+            #[cfg(not(unix))]
+            Address::Unix(_) => Err(self.unsupported()),
+        }
+    }
+
+    pub(crate) async fn bind(&self) -> Result<Listener, io::Error> {
+        match self {
+            Address::Tcp(addr) => Ok(Listener::Tcp(TcpListener::bind(addr).await?)),
+            #[cfg(unix)]
+            Address::Unix(path) => {
+                // A socket file left behind by a previous run would make the
+                // bind fail
+                if std::fs::metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+                    std::fs::remove_file(path)?;
+                }
+                Ok(Listener::Unix(UnixListener::bind(path)?))
+            }
+            #[cfg(not(unix))]
+            Address::Unix(_) => Err(self.unsupported()),
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn unsupported(&self) -> io::Error {
+        io::Error::new(io::ErrorKind::Unsupported, format!("unsupported address {}: Unix sockets need a Unix platform", self))
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Address::Tcp(addr) => f.write_str(addr),
+            Address::Unix(path) => write!(f, "{}{}", UNIX_SCHEME, path.display()),
+        }
+    }
+}
+
+pub(crate) enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+impl Listener {
+    /// Accepts the next connection, along with a description of the peer
+    /// for logging.
+    pub(crate) async fn accept(&self) -> Result<(BoxedStream, String), io::Error> {
+        match self {
+            Listener::Tcp(listener) => {
+                let (socket, peer) = listener.accept().await?;
+                Ok((Box::new(socket), peer.to_string()))
+            }
+            #[cfg(unix)]
+            Listener::Unix(listener) => {
+                let (socket, peer) = listener.accept().await?;
+                let peer = match peer.as_pathname() {
+                    Some(path) => format!("{}{}", UNIX_SCHEME, path.display()),
+                    None => "unix socket peer".to_string(),
+                };
+                Ok((Box::new(socket), peer))
+            }
+        }
+    }
+}
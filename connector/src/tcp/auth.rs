@@ -0,0 +1,57 @@
+use std::fmt;
+use ring::constant_time::verify_slices_are_equal;
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+
+/// Length of the nonce a server challenges new connections with.
+const NONCE_LEN: usize = 32;
+
+/// How a client proves it may send to a server. Both ends must be
+/// configured with the same secret.
+#[derive(Clone)]
+pub enum Authentication {
+    /// The client answers the challenge with the token itself. Only safe
+    /// over TLS.
+    Token(String),
+    /// The client answers with an HMAC-SHA256 of the challenge, so the
+    /// secret never crosses the wire.
+    Hmac(Vec<u8>),
+}
+
+impl Authentication {
+    pub(crate) fn challenge() -> Vec<u8> {
+        let mut nonce = vec![0; NONCE_LEN];
+        SystemRandom::new().fill(&mut nonce).expect("system random number generator failed");
+        nonce
+    }
+
+    pub(crate) fn proof(&self, challenge: &[u8]) -> Vec<u8> {
+        match self {
+            Authentication::Token(token) => token.as_bytes().to_vec(),
+            Authentication::Hmac(secret) => {
+                let key = hmac::Key::new(hmac::HMAC_SHA256, secret);
+                hmac::sign(&key, challenge).as_ref().to_vec()
+            }
+        }
+    }
+
+    /// Checks `proof` in constant time.
+    pub(crate) fn verify(&self, challenge: &[u8], proof: &[u8]) -> bool {
+        match self {
+            Authentication::Token(token) => verify_slices_are_equal(token.as_bytes(), proof).is_ok(),
+            Authentication::Hmac(secret) => {
+                let key = hmac::Key::new(hmac::HMAC_SHA256, secret);
+                hmac::verify(&key, challenge, proof).is_ok()
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Authentication {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Authentication::Token(_) => f.write_str("Token(..)"),
+            Authentication::Hmac(_) => f.write_str("Hmac(..)"),
+        }
+    }
+}
@@ -1,73 +1,149 @@
-use tokio::net::TcpStream;
 use tokio_util::codec::Framed;
-use futures::SinkExt;
-use tokio::sync::mpsc;
-use crate::{Message, MessageCodec};
+use futures::{SinkExt, StreamExt};
+use log::{debug, error, info, warn};
+use tokio::sync::{mpsc, watch};
+use tokio::time::{interval, interval_at, sleep, sleep_until, Instant};
+use crate::{CompressionPolicy, Frame, FrameCodec, Message, MessageBatch, MessageCodec, ZstdDictionaries, DEFAULT_MAX_FRAME_SIZE};
+use std::collections::{HashMap, HashSet};
 use std::time::Duration;
-use std::io::ErrorKind;
+use uuid::Uuid;
+use super::{Address, Authentication, ClientTls, ConnectionState, ReconnectPolicy};
+use super::spool::{Spool, SpoolOptions};
+use super::stream::BoxedStream;
+
+/// How often unacknowledged messages are checked for a resend.
+const RESEND_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Most messages written to the spool at once while disconnected.
+const SPOOL_BATCH_SIZE: usize = 100;
+
+/// How long to wait for the server's authentication challenge.
+const CHALLENGE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// When to flush the messages collected for a batch frame. Whichever limit
+/// is reached first triggers the flush.
+#[derive(Debug, Clone)]
+pub struct BatchOptions {
+    pub max_messages: usize,
+    /// Flush once the payloads add up to roughly this many bytes. Never
+    /// more than the client's `max_frame_size`.
+    pub max_bytes: usize,
+    /// How long the first message of a batch may wait for others.
+    pub linger: Duration,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        BatchOptions {
+            max_messages: 100,
+            max_bytes: 1024 * 1024,
+            linger: Duration::from_millis(50),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ClientOptions {
+    /// Frames larger than this are refused before being sent.
+    pub max_frame_size: usize,
+    pub compression_policy: CompressionPolicy,
+    /// Dictionaries available to messages sent with `Compression::ZstdDict`.
+    pub dictionaries: ZstdDictionaries,
+    /// Groups messages into batch frames; `None` sends one frame per message.
+    pub batch: Option<BatchOptions>,
+    /// Messages not acknowledged by the server within this time are sent
+    /// again, as are messages it rejects. `None` treats a successful write
+    /// as delivery.
+    pub ack_timeout: Option<Duration>,
+    /// Stop taking new messages while this many await acknowledgement.
+    pub max_in_flight: usize,
+    /// Connect over TLS.
+    pub tls: Option<ClientTls>,
+    /// Answer the server's authentication challenge with this secret.
+    pub authentication: Option<Authentication>,
+    /// Keep undelivered messages on disk while the server is unreachable,
+    /// so they survive a restart.
+    pub spool: Option<SpoolOptions>,
+    pub reconnect: ReconnectPolicy,
+    /// Ping the server this often, so it does not consider the connection
+    /// idle.
+    pub heartbeat_interval: Option<Duration>,
+    /// Reconnect when nothing, not even a pong, has been received for this
+    /// long. Must exceed `heartbeat_interval`.
+    pub idle_timeout: Option<Duration>,
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        ClientOptions {
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            compression_policy: CompressionPolicy::default(),
+            dictionaries: ZstdDictionaries::default(),
+            batch: None,
+            ack_timeout: Some(Duration::from_secs(30)),
+            max_in_flight: 1000,
+            tls: None,
+            authentication: None,
+            spool: None,
+            reconnect: ReconnectPolicy::default(),
+            heartbeat_interval: Some(Duration::from_secs(15)),
+            idle_timeout: Some(Duration::from_secs(45)),
+        }
+    }
+}
 
 pub struct Client {
     addr: String,
     receiver: mpsc::Receiver<Message>,
+    options: ClientOptions,
+    state: watch::Sender<ConnectionState>,
 }
 
 impl Client {
     pub fn new(addr: &str, receiver: mpsc::Receiver<Message>) -> Self {
+        Self::with_options(addr, receiver, ClientOptions::default())
+    }
+
+    pub fn with_options(addr: &str, receiver: mpsc::Receiver<Message>, options: ClientOptions) -> Self {
         Client {
             addr: addr.to_string(),
             receiver,
+            options,
+            state: watch::channel(ConnectionState::Connecting).0,
         }
     }
 
+    /// Follows the state of the connection to the server.
+    pub fn state(&self) -> watch::Receiver<ConnectionState> {
+        self.state.subscribe()
+    }
+
     pub async fn start(mut self) {
-        let addr = self.addr.clone();
-        let (msg_tx, mut msg_rx) = mpsc::channel::<Message>(100);
+        let codec = MessageCodec::new()
+            .with_max_frame_size(self.options.max_frame_size)
+            .with_compression_policy(self.options.compression_policy.clone())
+            .with_dictionaries(self.options.dictionaries.clone());
+        let (msg_tx, msg_rx) = mpsc::channel::<Message>(100);
+        let spool = self.options.spool.as_ref().and_then(|options| {
+            Spool::open(options, &self.addr, codec.clone())
+                .map_err(|e| warn!("Failed to open spool for {}, keeping messages in memory: {:?}", self.addr, e))
+                .ok()
+        });
 
         // Task to manage the socket connection
+        let mut connection = Connection {
+            addr: self.addr.clone(),
+            codec: FrameCodec::from(codec),
+            options: self.options.clone(),
+            msg_rx,
+            in_flight: HashMap::new(),
+            pending_messages: Vec::new(),
+            spool,
+            replaying: HashSet::new(),
+            state: self.state.clone(),
+        };
         tokio::spawn(async move {
-            let mut pending_messages = vec![];
-
-            loop {
-                match TcpStream::connect(&addr).await {
-                    Ok(socket) => {
-                        let mut framed = Framed::new(socket, MessageCodec);
-
-                        // Resend pending messages
-                        for message in pending_messages.drain(..) {
-                            if let Err(e) = framed.send(message).await {
-                                if e.kind() == ErrorKind::BrokenPipe {
-                                    eprintln!("Broken pipe while resending, reconnecting to {}: {:?}", addr, e);
-                                    break;
-                                } else {
-                                    eprintln!("Failed to resend message to {}: {:?}", addr, e);
-                                }
-                            } else {
-                                println!("Pending message sent to {}", addr);
-                            }
-                        }
-
-                        while let Some(message) = msg_rx.recv().await {
-                            if let Err(e) = framed.send(message.clone()).await {
-                                if e.kind() == ErrorKind::BrokenPipe {
-                                    eprintln!("Broken pipe, attempting to reconnect to {}: {:?}", addr, e);
-                                    pending_messages.push(message);
-                                    break; // Exit the loop to reconnect
-                                } else {
-                                    eprintln!("Failed to send message to {}: {:?}", addr, e);
-                                }
-                            } else {
-                                println!("Message sent to {}", addr);
-                            }
-                        }
-                    },
-                    Err(e) => {
-                        eprintln!("Failed to connect to {}: {:?}", addr, e);
-                    },
-                }
-
-                // Wait before attempting to reconnect
-                tokio::time::sleep(Duration::from_secs(5)).await;
-            }
+            connection.run().await;
         });
 
         // Task to receive messages and forward them to the socket handling task
@@ -76,10 +152,422 @@ impl Client {
                 let msg_tx_clone = msg_tx.clone();
                 tokio::spawn(async move {
                     if let Err(e) = msg_tx_clone.send(message).await {
-                        eprintln!("Failed to forward message: {:?}", e);
+                        error!("Failed to forward message: {:?}", e);
                     }
                 });
             }
         });
     }
 }
+
+type Transport = Framed<BoxedStream, FrameCodec>;
+
+struct InFlight {
+    message: Message,
+    resend_at: Instant,
+}
+
+/// Delivers messages over one connection at a time, reconnecting when it
+/// breaks, and keeps every message until the server acknowledges it.
+struct Connection {
+    addr: String,
+    codec: FrameCodec,
+    options: ClientOptions,
+    msg_rx: mpsc::Receiver<Message>,
+    /// Written but not yet acknowledged, by message id.
+    in_flight: HashMap<Uuid, InFlight>,
+    /// Could not be written; sent first once reconnected.
+    pending_messages: Vec<Message>,
+    /// Holds undelivered messages while disconnected, when configured.
+    spool: Option<Spool>,
+    /// Replayed from the spool but not yet delivered, by message id.
+    replaying: HashSet<Uuid>,
+    state: watch::Sender<ConnectionState>,
+}
+
+/// Messages collected for the next batch frame.
+#[derive(Default)]
+struct Batch {
+    messages: Vec<Message>,
+    bytes: usize,
+    deadline: Option<Instant>,
+}
+
+impl Connection {
+    async fn run(&mut self) {
+        let mut failures = 0;
+        loop {
+            self.set_state(ConnectionState::Connecting);
+            match self.open().await {
+                Ok(mut transport) => {
+                    if failures > 0 {
+                        info!("Reconnected to {} after {} failed attempts", self.addr, failures);
+                    } else {
+                        info!("Connected to {}", self.addr);
+                    }
+                    failures = 0;
+                    self.set_state(ConnectionState::Connected);
+                    if !self.serve(&mut transport).await {
+                        self.set_state(ConnectionState::Closed);
+                        return;
+                    }
+                    self.set_state(ConnectionState::Disconnected);
+                },
+                Err(reason) => {
+                    failures += 1;
+                    // Only the first failure of an outage is worth a warning
+                    if failures == 1 {
+                        warn!("Failed to connect to {}: {}", self.addr, reason);
+                    } else {
+                        debug!("Failed to connect to {} ({} attempts): {}", self.addr, failures, reason);
+                    }
+                    self.set_state(ConnectionState::Disconnected);
+                },
+            }
+
+            self.spool_undelivered().await;
+            if !self.options.reconnect.should_retry(failures) {
+                error!("Giving up on {} after {} failed attempts", self.addr, failures);
+                self.set_state(ConnectionState::Failed);
+                return;
+            }
+            let delay = self.options.reconnect.delay(failures.max(1));
+            self.wait_to_reconnect(delay).await;
+        }
+    }
+
+    /// Publishes `state`, if it differs from the current one.
+    fn set_state(&self, state: ConnectionState) {
+        self.state.send_if_modified(|current| std::mem::replace(current, state) != state);
+    }
+
+    /// Connects and authenticates.
+    async fn open(&self) -> Result<Transport, String> {
+        let socket = self.connect().await.map_err(|e| e.to_string())?;
+        let mut transport = Framed::new(socket, self.codec.clone());
+        self.authenticate(&mut transport).await
+            .map_err(|reason| format!("authentication failed: {}", reason))?;
+        Ok(transport)
+    }
+
+    /// Moves the messages the connection left undelivered to the spool.
+    async fn spool_undelivered(&mut self) {
+        let Some(spool) = &self.spool else {
+            return;
+        };
+        // Without a replay running, the replay file may be one a crash left
+        // unread, and must be kept
+        let replaying = !self.replaying.is_empty();
+        let mut undelivered: Vec<Message> = self.pending_messages.drain(..).collect();
+        undelivered.extend(self.in_flight.drain().map(|(_, in_flight)| redelivery(in_flight.message)));
+        undelivered.sort_by_key(|message| message.envelope.created_at);
+        match spool.append(undelivered).await {
+            // The replayed messages still undelivered were spooled again
+            Ok(()) if replaying => self.finish_replay().await,
+            Ok(()) => {}
+            Err((unspooled, e)) => {
+                warn!("Failed to spool {} messages, keeping them in memory: {}", unspooled.len(), e);
+                self.pending_messages.extend(unspooled);
+            }
+        }
+    }
+
+    /// Waits before reconnecting, spooling the messages that arrive in the
+    /// meantime.
+    async fn wait_to_reconnect(&mut self, delay: Duration) {
+        let Some(spool) = &self.spool else {
+            sleep(delay).await;
+            return;
+        };
+        let deadline = Instant::now() + delay;
+        let mut received = Vec::new();
+        loop {
+            tokio::select! {
+                _ = sleep_until(deadline) => return,
+                count = self.msg_rx.recv_many(&mut received, SPOOL_BATCH_SIZE) => {
+                    if count == 0 {
+                        sleep_until(deadline).await;
+                        return;
+                    }
+                    if let Err((unspooled, e)) = spool.append(std::mem::take(&mut received)).await {
+                        warn!("Failed to spool {} messages, keeping them in memory: {}", unspooled.len(), e);
+                        self.pending_messages.extend(unspooled);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Notes that a replayed message no longer needs the spool, forgetting
+    /// the replay once all of them are delivered.
+    async fn delivered(&mut self, id: &Uuid) {
+        if self.replaying.remove(id) && self.replaying.is_empty() {
+            self.finish_replay().await;
+        }
+    }
+
+    async fn finish_replay(&mut self) {
+        self.replaying.clear();
+        if let Some(spool) = &self.spool {
+            if let Err(e) = spool.replayed().await {
+                warn!("Failed to clear replayed spool for {}: {}", self.addr, e);
+            }
+        }
+    }
+
+    async fn connect(&self) -> Result<BoxedStream, std::io::Error> {
+        let socket = Address::parse(&self.addr).connect().await?;
+        match &self.options.tls {
+            Some(tls) => {
+                let server_name = tls.server_name(&self.addr)?;
+                Ok(Box::new(tls.connector().connect(server_name, socket).await?))
+            }
+            None => Ok(socket),
+        }
+    }
+
+    /// Answers the server's challenge, if the client is configured with
+    /// credentials. A server rejecting them closes the connection.
+    async fn authenticate(&self, transport: &mut Transport) -> Result<(), String> {
+        let Some(authentication) = &self.options.authentication else {
+            return Ok(());
+        };
+        let challenge = match tokio::time::timeout(CHALLENGE_TIMEOUT, transport.next()).await {
+            Ok(Some(Ok(Frame::Challenge(challenge)))) => challenge,
+            Ok(Some(Ok(frame))) => return Err(format!("expected a challenge, got {:?}", frame)),
+            Ok(Some(Err(e))) => return Err(e.to_string()),
+            Ok(None) => return Err("connection closed".to_string()),
+            Err(_) => return Err("no challenge received in time".to_string()),
+        };
+        transport.send(Frame::Auth(authentication.proof(&challenge))).await.map_err(|e| e.to_string())
+    }
+
+    /// Sends messages over `transport` until it breaks, returning `false`
+    /// once there is nothing left to deliver.
+    async fn serve(&mut self, transport: &mut Transport) -> bool {
+        // Replay the spool, then resend pending messages and those the
+        // previous connection left unacknowledged
+        let mut resend: Vec<Message> = match &self.spool {
+            Some(spool) => spool.replay().await.unwrap_or_else(|e| {
+                error!("Failed to read spool for {}: {:?}", self.addr, e);
+                Vec::new()
+            }),
+            None => Vec::new(),
+        };
+        self.replaying = resend.iter().map(|message| message.envelope.id).collect();
+        if self.replaying.is_empty() {
+            self.finish_replay().await;
+        }
+        resend.append(&mut self.pending_messages);
+        resend.extend(self.in_flight.drain().map(|(_, in_flight)| redelivery(in_flight.message)));
+        resend.sort_by_key(|message| message.envelope.created_at);
+        let mut resend = resend.into_iter();
+        while let Some(message) = resend.next() {
+            if !self.write(transport, message).await {
+                self.pending_messages.extend(resend);
+                return true;
+            }
+        }
+
+        let mut batch = Batch::default();
+        let mut closed = false;
+        let mut resend_check = interval(RESEND_CHECK_INTERVAL);
+        // Without a heartbeat the timer is never polled; any period will do
+        let heartbeat_period = self.options.heartbeat_interval.unwrap_or(RESEND_CHECK_INTERVAL);
+        let mut heartbeat = interval_at(Instant::now() + heartbeat_period, heartbeat_period);
+        let mut last_received = Instant::now();
+
+        loop {
+            let backlogged = self.in_flight.len() >= self.options.max_in_flight;
+            self.set_state(if backlogged { ConnectionState::Backlogged } else { ConnectionState::Connected });
+            let accepting = !closed && !backlogged;
+            let linger = batch.deadline.unwrap_or_else(Instant::now);
+            let idle_deadline = self.options.idle_timeout.map(|idle_timeout| last_received + idle_timeout);
+
+            tokio::select! {
+                received = self.msg_rx.recv(), if accepting => match received {
+                    Some(message) => {
+                        let Some(options) = self.options.batch.clone() else {
+                            if !self.write(transport, message).await {
+                                return true;
+                            }
+                            continue;
+                        };
+                        let max_bytes = options.max_bytes.min(self.options.max_frame_size);
+                        let size = message.estimated_size();
+                        if !batch.messages.is_empty() && batch.bytes + size > max_bytes
+                            && !self.write_batch(transport, &mut batch).await {
+                            self.pending_messages.push(message);
+                            return true;
+                        }
+                        batch.deadline.get_or_insert_with(|| Instant::now() + options.linger);
+                        batch.bytes += size;
+                        batch.messages.push(message);
+                        if (batch.messages.len() >= options.max_messages || batch.bytes >= max_bytes)
+                            && !self.write_batch(transport, &mut batch).await {
+                            return true;
+                        }
+                    }
+                    None => {
+                        closed = true;
+                        if !self.write_batch(transport, &mut batch).await {
+                            return true;
+                        }
+                    }
+                },
+                _ = sleep_until(linger), if batch.deadline.is_some() => {
+                    if !self.write_batch(transport, &mut batch).await {
+                        return true;
+                    }
+                }
+                frame = transport.next() => {
+                    if let Some(Ok(_)) = frame {
+                        last_received = Instant::now();
+                    }
+                    match frame {
+                        Some(Ok(Frame::Pong)) => {}
+                        Some(Ok(Frame::Ack(id))) => {
+                            self.in_flight.remove(&id);
+                            self.delivered(&id).await;
+                        }
+                        Some(Ok(Frame::Nack { id, reason })) => {
+                            warn!("Message {} rejected by {}: {}", id, self.addr, reason);
+                            if let Some(in_flight) = self.in_flight.get_mut(&id) {
+                                // Due for a resend at the next check
+                                in_flight.resend_at = Instant::now();
+                            }
+                        }
+                        Some(Ok(frame)) => warn!("Ignoring unexpected frame from {}: {:?}", self.addr, frame),
+                        Some(Err(e)) => {
+                            warn!("Connection to {} failed, reconnecting: {}", self.addr, e);
+                            return true;
+                        }
+                        None => {
+                            warn!("Connection to {} closed, reconnecting", self.addr);
+                            return true;
+                        }
+                    }
+                },
+                _ = heartbeat.tick(), if self.options.heartbeat_interval.is_some() => {
+                    if let Err(e) = transport.send(Frame::Ping).await {
+                        warn!("Failed to ping {}, reconnecting: {}", self.addr, e);
+                        return true;
+                    }
+                }
+                _ = sleep_until(idle_deadline.unwrap_or_else(Instant::now)), if idle_deadline.is_some() => {
+                    warn!("Nothing received from {} for {:?}, reconnecting", self.addr, self.options.idle_timeout.unwrap_or_default());
+                    return true;
+                }
+                _ = resend_check.tick(), if self.options.ack_timeout.is_some() => {
+                    if !self.resend_expired(transport).await {
+                        return true;
+                    }
+                }
+            }
+
+            if closed && batch.messages.is_empty() && self.in_flight.is_empty() {
+                return false;
+            }
+        }
+    }
+
+    /// Writes one message, returning `false` if the connection is broken.
+    /// A message that cannot be encoded is dropped; any other failure keeps
+    /// it for the next connection.
+    async fn write(&mut self, transport: &mut Transport, message: Message) -> bool {
+        match transport.send(Frame::Message(message.clone())).await {
+            Ok(()) => {
+                debug!("Message sent to {}", self.addr);
+                self.track(message).await;
+                true
+            }
+            Err(e) if e.is_unencodable() => {
+                error!("Dropping message {} that cannot be sent to {}: {}", message.envelope.id, self.addr, e);
+                self.delivered(&message.envelope.id).await;
+                true
+            }
+            Err(e) => {
+                warn!("Failed to send message to {}, reconnecting: {}", self.addr, e);
+                self.pending_messages.push(redelivery(message));
+                false
+            }
+        }
+    }
+
+    /// Writes the collected messages as one frame, returning `false` if the
+    /// connection is broken. A batch that cannot be encoded, say because
+    /// the size estimate fell short of the frame limit, is split in halves
+    /// until only the offending messages are left to drop.
+    async fn write_batch(&mut self, transport: &mut Transport, batch: &mut Batch) -> bool {
+        let mut parts = vec![std::mem::take(batch).messages];
+        while let Some(mut messages) = parts.pop() {
+            if messages.len() == 1 {
+                if !self.write(transport, messages.remove(0)).await {
+                    self.pending_messages.extend(parts.into_iter().rev().flatten().map(redelivery));
+                    return false;
+                }
+                continue;
+            }
+            let Some(first) = messages.first() else {
+                continue;
+            };
+            let compression = first.compression.clone();
+            let count = messages.len();
+
+            match transport.send(MessageBatch::new(compression, messages.clone())).await {
+                Ok(()) => {
+                    debug!("Batch of {} messages sent to {}", count, self.addr);
+                    for message in messages {
+                        self.track(message).await;
+                    }
+                }
+                Err(e) if e.is_unencodable() => {
+                    warn!("Failed to encode batch of {} messages for {}, splitting it: {}", count, self.addr, e);
+                    let second_half = messages.split_off(count / 2);
+                    parts.push(second_half);
+                    parts.push(messages);
+                }
+                Err(e) => {
+                    warn!("Failed to send batch of {} messages to {}, reconnecting: {}", count, self.addr, e);
+                    self.pending_messages.extend(messages.into_iter().chain(parts.into_iter().rev().flatten()).map(redelivery));
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Sends again the messages whose acknowledgement is overdue.
+    async fn resend_expired(&mut self, transport: &mut Transport) -> bool {
+        let now = Instant::now();
+        let expired: Vec<Uuid> = self.in_flight.iter()
+            .filter(|(_, in_flight)| in_flight.resend_at <= now)
+            .map(|(id, _)| *id)
+            .collect();
+
+        for id in expired {
+            if let Some(in_flight) = self.in_flight.remove(&id) {
+                info!("Message {} not acknowledged by {}, resending", id, self.addr);
+                if !self.write(transport, redelivery(in_flight.message)).await {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Waits for the server to acknowledge `message`, if it is expected to.
+    async fn track(&mut self, message: Message) {
+        match self.options.ack_timeout {
+            Some(ack_timeout) => {
+                self.in_flight.insert(message.envelope.id, InFlight { message, resend_at: Instant::now() + ack_timeout });
+            }
+            None => self.delivered(&message.envelope.id).await,
+        }
+    }
+}
+
+fn redelivery(mut message: Message) -> Message {
+    message.envelope.redeliveries += 1;
+    message
+}
@@ -1,2 +1,22 @@
 pub mod server;
-pub mod client;
\ No newline at end of file
+pub mod client;
+
+mod ack;
+pub use ack::Acknowledgements;
+
+mod address;
+pub use address::Address;
+
+mod auth;
+pub use auth::Authentication;
+
+mod tls;
+pub use tls::{ClientTls, ServerTls};
+
+mod reconnect;
+pub use reconnect::{ConnectionState, ReconnectPolicy};
+
+mod spool;
+pub use spool::SpoolOptions;
+
+mod stream;
@@ -0,0 +1,76 @@
+use std::time::Duration;
+use rand::Rng;
+
+/// How long a `Client` waits between connection attempts: the delay grows
+/// by `multiplier` after every failure, up to `max_delay`, and is spread by
+/// `jitter` so clients that lost the same server do not all return at once.
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub multiplier: f64,
+    /// Fraction of the delay added or removed at random, between 0 and 1.
+    pub jitter: f64,
+    /// Give up after this many consecutive failures; `None` retries forever.
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            multiplier: 2.0,
+            jitter: 0.2,
+            max_attempts: None,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Delay before retrying after `failures` consecutive failed attempts.
+    pub fn delay(&self, failures: u32) -> Duration {
+        let exponent = failures.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let base = self.initial_delay.as_secs_f64() * self.multiplier.powi(exponent);
+        let base = base.min(self.max_delay.as_secs_f64());
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        let spread = if jitter > 0.0 { rand::thread_rng().gen_range(-jitter..=jitter) } else { 0.0 };
+        Duration::from_secs_f64((base * (1.0 + spread)).min(self.max_delay.as_secs_f64()))
+    }
+
+    /// Whether another attempt is allowed after `failures` consecutive
+    /// failures.
+    pub fn should_retry(&self, failures: u32) -> bool {
+        self.max_attempts.is_none_or(|max_attempts| failures < max_attempts)
+    }
+}
+
+/// Where a `Client` stands with its server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connecting,
+    /// Connected, and authenticated if required.
+    Connected,
+    /// Connected, but `max_in_flight` messages await acknowledgement, so no
+    /// more are taken for now.
+    Backlogged,
+    /// Waiting to reconnect after the connection failed or broke.
+    Disconnected,
+    /// The reconnect policy ran out of attempts; the client has stopped.
+    Failed,
+    /// Its sender hung up and every message was delivered; the client has
+    /// stopped.
+    Closed,
+}
+
+impl ConnectionState {
+    /// Whether the client can take messages right away.
+    pub fn is_healthy(&self) -> bool {
+        *self == ConnectionState::Connected
+    }
+
+    /// Whether the client has stopped for good.
+    pub fn is_stopped(&self) -> bool {
+        matches!(self, ConnectionState::Failed | ConnectionState::Closed)
+    }
+}
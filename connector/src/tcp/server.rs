@@ -1,55 +1,262 @@
-use tokio::net::{TcpListener, TcpStream};
 use tokio::io;
+use std::time::Duration;
 use tokio_util::codec::Framed;
-use tokio_stream::StreamExt;
+use futures::{SinkExt, StreamExt};
 use tokio::sync::mpsc;
-use crate::{Message, Protocol, MessageCodec};
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+use crate::{Frame, FrameCodec, Message, Protocol, MessageCodec, ZstdDictionaries, DEFAULT_MAX_FRAME_SIZE};
+use super::{Acknowledgements, Address, Authentication, ServerTls};
+use super::stream::BoxedStream;
+
+/// How long a new connection has to complete the TLS handshake, and then
+/// to answer the authentication challenge.
+const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long to wait before accepting again after a failed accept, such as
+/// when out of file descriptors.
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Once shutting down, frames arriving within this window are still handled
+/// before connections are closed.
+const DRAIN_GRACE: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone)]
+pub struct ServerOptions {
+    /// Frames larger than this are rejected and the connection is dropped.
+    pub max_frame_size: usize,
+    /// Dictionaries used to decode frames compressed against one; frames
+    /// referencing any other dictionary are rejected.
+    pub dictionaries: ZstdDictionaries,
+    /// When set, each message is acknowledged through it once it has been
+    /// handled. Otherwise it is acknowledged as soon as it is forwarded.
+    pub acknowledgements: Option<Acknowledgements>,
+    /// Accept only TLS connections.
+    pub tls: Option<ServerTls>,
+    /// Require clients to prove they know this secret before sending.
+    pub authentication: Option<Authentication>,
+    /// Close connections nothing has been received on for this long. Must
+    /// exceed the clients' heartbeat interval. Off by default, as clients
+    /// without heartbeats may stay silent for longer.
+    pub idle_timeout: Option<Duration>,
+}
+
+impl Default for ServerOptions {
+    fn default() -> Self {
+        ServerOptions {
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            dictionaries: ZstdDictionaries::default(),
+            acknowledgements: None,
+            tls: None,
+            authentication: None,
+            idle_timeout: None,
+        }
+    }
+}
+
+type Transport = Framed<BoxedStream, FrameCodec>;
 
 pub struct Server {
     addr: String,
     sender: mpsc::Sender<Message>,
+    options: ServerOptions,
+    cancellation_token: CancellationToken,
 }
 
 impl Server {
     pub fn new(addr: &str, sender: mpsc::Sender<Message>) -> Self {
+        Self::with_options(addr, sender, ServerOptions::default())
+    }
+
+    pub fn with_options(addr: &str, sender: mpsc::Sender<Message>, options: ServerOptions) -> Self {
         Server {
             addr: addr.to_string(),
             sender,
+            options,
+            cancellation_token: CancellationToken::new(),
         }
     }
 
+    /// Shuts the server down once `cancellation_token` is cancelled.
+    pub fn with_cancellation_token(mut self, cancellation_token: CancellationToken) -> Self {
+        self.cancellation_token = cancellation_token;
+        self
+    }
+
+    /// Stops accepting connections and reading frames. `start` returns once
+    /// every connection is closed.
+    pub fn stop(&self) {
+        self.cancellation_token.cancel()
+    }
+
     pub async fn start(&self) -> io::Result<()> {
-        let listener = TcpListener::bind(&self.addr).await?;
+        let address = Address::parse(&self.addr);
+        let listener = address.bind().await?;
         println!("Server listening on {}", &self.addr);
 
+        let mut connections = JoinSet::new();
         loop {
-            let (socket, _) = listener.accept().await?;
+            let (socket, peer) = tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        // Usually transient; giving up would drop every open connection
+                        println!("Failed to accept a connection on {}: {}", self.addr, e);
+                        tokio::select! {
+                            _ = tokio::time::sleep(ACCEPT_RETRY_DELAY) => continue,
+                            _ = self.cancellation_token.cancelled() => break,
+                        }
+                    }
+                },
+                Some(_) = connections.join_next() => continue,
+                _ = self.cancellation_token.cancelled() => break,
+            };
             let sender = self.sender.clone();
-            tokio::spawn(Self::handle_client(socket, sender));
+            let codec = MessageCodec::new()
+                .with_max_frame_size(self.options.max_frame_size)
+                .with_dictionaries(self.options.dictionaries.clone());
+            let acknowledgements = self.options.acknowledgements.clone();
+            let tls = self.options.tls.clone();
+            let authentication = self.options.authentication.clone();
+            let idle_timeout = self.options.idle_timeout;
+            let shutdown = self.cancellation_token.clone();
+            connections.spawn(async move {
+                let stream: BoxedStream = match tls {
+                    Some(tls) => match tokio::time::timeout(AUTH_TIMEOUT, tls.acceptor().accept(socket)).await {
+                        Ok(Ok(stream)) => Box::new(stream),
+                        Ok(Err(e)) => {
+                            println!("TLS handshake with {} failed: {}", peer, e);
+                            return;
+                        }
+                        Err(_) => {
+                            println!("TLS handshake with {} timed out", peer);
+                            return;
+                        }
+                    },
+                    None => socket,
+                };
+                let mut transport = Framed::new(stream, FrameCodec::from(codec));
+                if let Some(authentication) = authentication {
+                    if let Err(reason) = Self::authenticate(&mut transport, &authentication).await {
+                        println!("Authentication of {} failed, closing connection: {}", peer, reason);
+                        return;
+                    }
+                }
+                Self::handle_client(transport, peer, sender, acknowledgements, idle_timeout, shutdown).await;
+            });
         }
+
+        drop(listener);
+        if let Address::Unix(path) = &address {
+            let _ = std::fs::remove_file(path);
+        }
+        println!("Server on {} stopped accepting connections, draining {} open", &self.addr, connections.len());
+        while connections.join_next().await.is_some() {}
+        Ok(())
     }
 
-    async fn handle_client(socket: TcpStream, sender: mpsc::Sender<Message>) -> Result<(), io::Error> {
-        let mut framed = Framed::new(socket, MessageCodec);
+    /// Challenges the client and checks its answer.
+    async fn authenticate(transport: &mut Transport, authentication: &Authentication) -> Result<(), String> {
+        let challenge = Authentication::challenge();
+        transport.send(Frame::Challenge(challenge.clone())).await.map_err(|e| e.to_string())?;
+
+        match tokio::time::timeout(AUTH_TIMEOUT, transport.next()).await {
+            Ok(Some(Ok(Frame::Auth(proof)))) if authentication.verify(&challenge, &proof) => Ok(()),
+            Ok(Some(Ok(Frame::Auth(_)))) => Err("invalid credentials".to_string()),
+            Ok(Some(Ok(frame))) => Err(format!("expected credentials, got {:?}", frame)),
+            Ok(Some(Err(e))) => Err(e.to_string()),
+            Ok(None) => Err("connection closed".to_string()),
+            Err(_) => Err("no credentials received in time".to_string()),
+        }
+    }
 
-        while let Some(result) = framed.next().await {
+    async fn handle_client(transport: Transport, peer: String, sender: mpsc::Sender<Message>, acknowledgements: Option<Acknowledgements>, idle_timeout: Option<Duration>, shutdown: CancellationToken) {
+        let (mut sink, mut stream) = transport.split();
+
+        // Acknowledgements are written by their own task, as they may arrive
+        // after this connection has moved on to later messages.
+        let (ack_tx, mut ack_rx) = mpsc::unbounded_channel::<Frame>();
+        let writer_peer = peer.clone();
+        let writer = tokio::spawn(async move {
+            while let Some(frame) = ack_rx.recv().await {
+                if let Err(e) = sink.send(frame).await {
+                    println!("Failed to acknowledge message to {}: {}", writer_peer, e);
+                    break;
+                }
+            }
+        });
+
+        let mut drain_until = None;
+        loop {
+            let next = tokio::select! {
+                next = async {
+                    match (drain_until, idle_timeout) {
+                        (Some(drain_until), _) => tokio::time::timeout_at(drain_until, stream.next()).await.ok(),
+                        (None, Some(idle_timeout)) => tokio::time::timeout(idle_timeout, stream.next()).await.ok(),
+                        (None, None) => Some(stream.next().await),
+                    }
+                } => next,
+                _ = shutdown.cancelled(), if drain_until.is_none() => {
+                    drain_until = Some(tokio::time::Instant::now() + DRAIN_GRACE);
+                    continue;
+                }
+            };
+            let Some(next) = next else {
+                if drain_until.is_none() {
+                    println!("Nothing received from {} for {:?}, closing connection", peer, idle_timeout.unwrap_or_default());
+                }
+                break;
+            };
+            let Some(result) = next else {
+                break;
+            };
             match result {
-                Ok(message) => {
+                Ok(Frame::Message(message)) => {
                     match message.payload {
-                        Protocol::Json(_) => {
-                            println!("Received JSON");
-                            if let Err(e) = sender.send(message).await {
-                                println!("Failed to forward message to DataSink: {:?}", e);
+                        Protocol::Json(_) => println!("Received JSON"),
+                        Protocol::MsgPack(_) => println!("Received MessagePack"),
+                    }
+                    let id = message.envelope.id;
+                    if let Some(acknowledgements) = &acknowledgements {
+                        acknowledgements.register(id, ack_tx.clone());
+                    }
+                    if let Err(e) = sender.send(message).await {
+                        println!("Failed to forward message to DataSink: {:?}", e);
+                        match &acknowledgements {
+                            Some(acknowledgements) => {
+                                acknowledgements.nack(&id, "sink unavailable");
+                            }
+                            None => {
+                                let _ = ack_tx.send(Frame::Nack { id, reason: "sink unavailable".to_string() });
                             }
                         }
+                    } else if acknowledgements.is_none() {
+                        let _ = ack_tx.send(Frame::Ack(id));
                     }
                 }
+                Ok(Frame::Ping) => {
+                    let _ = ack_tx.send(Frame::Pong);
+                }
+                Ok(frame) => println!("Ignoring unexpected frame from {}: {:?}", peer, frame),
                 Err(e) => {
-                    println!("Failed to decode message: {:?}", e);
+                    // The stream position is unknown after a bad frame, so the
+                    // connection cannot be resynchronised.
+                    println!("Failed to decode message from {}, closing connection: {}", peer, e);
+                    break;
                 }
             }
         }
 
-        Ok(())
+        if shutdown.is_cancelled() {
+            // Let the messages still being handled be acknowledged; the
+            // writer ends once the last of them is
+            drop(ack_tx);
+        } else {
+            writer.abort();
+        }
+        let _ = writer.await;
+        if let Some(acknowledgements) = &acknowledgements {
+            acknowledgements.prune();
+        }
     }
 }
@@ -0,0 +1,191 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use bytes::BytesMut;
+use log::warn;
+use tokio::io;
+use tokio::task;
+use tokio_util::codec::{Decoder, Encoder};
+use crate::{CodecError, Message, MessageCodec};
+
+/// Where and how much a `Client` may spool to disk while its server is
+/// unreachable.
+#[derive(Debug, Clone)]
+pub struct SpoolOptions {
+    /// Holds one spool file per server address.
+    pub dir: PathBuf,
+    /// Messages arriving once the spool file has reached this size are kept
+    /// in memory instead.
+    pub max_bytes: u64,
+    /// Spooled messages older than this are dropped instead of replayed.
+    pub max_age: Option<Duration>,
+}
+
+impl SpoolOptions {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        SpoolOptions {
+            dir: dir.into(),
+            max_bytes: 256 * 1024 * 1024,
+            max_age: Some(Duration::from_secs(24 * 3600)),
+        }
+    }
+}
+
+/// Append-only file of encoded frames, replayed on reconnect.
+///
+/// A replay first moves the spool aside to a `.replay` file, which is only
+/// removed once every replayed message has been delivered. Messages
+/// spooled meanwhile go to a fresh spool file, and a crash or disconnect
+/// during the replay leaves the `.replay` file to be read again.
+#[derive(Clone)]
+pub(crate) struct Spool {
+    path: PathBuf,
+    replay_path: PathBuf,
+    options: SpoolOptions,
+    codec: MessageCodec,
+}
+
+impl Spool {
+    /// Opens the spool for `addr`, picking up whatever a previous run left.
+    pub(crate) fn open(options: &SpoolOptions, addr: &str, codec: MessageCodec) -> Result<Self, io::Error> {
+        fs::create_dir_all(&options.dir)?;
+        let name: String = addr.chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+            .collect();
+        Ok(Spool {
+            path: options.dir.join(format!("{}.spool", name)),
+            replay_path: options.dir.join(format!("{}.spool.replay", name)),
+            options: options.clone(),
+            codec,
+        })
+    }
+
+    /// Appends `messages` with a single write and sync, off the async
+    /// runtime. On failure, returns the messages that were not spooled:
+    /// those past a full spool, or all of them.
+    pub(crate) async fn append(&self, messages: Vec<Message>) -> Result<(), (Vec<Message>, CodecError)> {
+        if messages.is_empty() {
+            return Ok(());
+        }
+        let mut spool = self.clone();
+        task::spawn_blocking(move || spool.append_blocking(messages))
+            .await
+            .unwrap_or_else(|e| Err((Vec::new(), io::Error::other(e).into())))
+    }
+
+    fn append_blocking(&mut self, mut messages: Vec<Message>) -> Result<(), (Vec<Message>, CodecError)> {
+        let len = fs::metadata(&self.path).map_or(0, |metadata| metadata.len());
+        let mut buf = BytesMut::new();
+        let mut spooled = 0;
+        let mut rejected = None;
+        for message in &messages {
+            let start = buf.len();
+            if let Err(e) = self.codec.encode(message.clone(), &mut buf) {
+                rejected = Some(e);
+                break;
+            }
+            if len + buf.len() as u64 > self.options.max_bytes {
+                buf.truncate(start);
+                rejected = Some(io::Error::other(format!("spool {} is full", self.path.display())).into());
+                break;
+            }
+            spooled += 1;
+        }
+
+        let written = OpenOptions::new().create(true).append(true).open(&self.path)
+            .and_then(|mut file| {
+                file.write_all(&buf)?;
+                file.sync_data()
+            });
+        if let Err(e) = written {
+            return Err((messages, e.into()));
+        }
+        match rejected {
+            Some(e) => Err((messages.split_off(spooled), e)),
+            None => Ok(()),
+        }
+    }
+
+    /// Reads back the spooled messages in the order they were written,
+    /// including those of an earlier replay that did not finish. They stay
+    /// on disk until `replayed` is called.
+    pub(crate) async fn replay(&self) -> Result<Vec<Message>, io::Error> {
+        let mut spool = self.clone();
+        task::spawn_blocking(move || spool.replay_blocking())
+            .await
+            .map_err(io::Error::other)?
+    }
+
+    fn replay_blocking(&mut self) -> Result<Vec<Message>, io::Error> {
+        let unfinished = self.replay_path.exists();
+        if !unfinished {
+            match fs::rename(&self.path, &self.replay_path) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+                Err(e) => return Err(e),
+            }
+        }
+
+        let mut messages = read_frames(&mut self.codec, &self.replay_path)?;
+        if unfinished && self.path.exists() {
+            // Fold what was spooled since into the unfinished replay, writing
+            // a new replay file so that neither is lost to a crash
+            messages.extend(read_frames(&mut self.codec, &self.path)?);
+            let mut buf = BytesMut::new();
+            for message in &messages {
+                self.codec.encode(message.clone(), &mut buf).map_err(io::Error::other)?;
+            }
+            let staged = self.replay_path.with_extension("replay.tmp");
+            let mut file = File::create(&staged)?;
+            file.write_all(&buf)?;
+            file.sync_data()?;
+            fs::rename(&staged, &self.replay_path)?;
+            fs::remove_file(&self.path)?;
+        }
+
+        if let Some(max_age) = self.options.max_age {
+            let now = SystemTime::now();
+            let count = messages.len();
+            messages.retain(|message| now.duration_since(message.envelope.created_at).map_or(true, |age| age <= max_age));
+            if messages.len() < count {
+                warn!("Dropped {} spooled messages older than {:?}", count - messages.len(), max_age);
+            }
+        }
+
+        Ok(messages)
+    }
+
+    /// Forgets the messages of the last replay, once they were all delivered
+    /// or spooled again.
+    pub(crate) async fn replayed(&self) -> Result<(), io::Error> {
+        let replay_path = self.replay_path.clone();
+        task::spawn_blocking(move || match fs::remove_file(replay_path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        })
+        .await
+        .map_err(io::Error::other)?
+    }
+}
+
+/// Decodes the frames of a spool file. A frame cut short by a crash ends
+/// the file.
+fn read_frames(codec: &mut MessageCodec, path: &Path) -> Result<Vec<Message>, io::Error> {
+    let mut buf = BytesMut::from(&fs::read(path)?[..]);
+    let mut messages = Vec::new();
+    loop {
+        match codec.decode(&mut buf) {
+            Ok(Some(message)) => messages.push(message),
+            Ok(None) => break,
+            Err(e) => {
+                warn!("Spool {} is corrupt past {} messages: {}", path.display(), messages.len(), e);
+                break;
+            }
+        }
+    }
+    if !buf.is_empty() {
+        warn!("Discarding {} trailing bytes of spool {}", buf.len(), path.display());
+    }
+    Ok(messages)
+}
@@ -0,0 +1,8 @@
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// A connection, whether plain or wrapped in TLS.
+pub(crate) trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+pub(crate) type BoxedStream = Box<dyn Stream>;
@@ -0,0 +1,155 @@
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+use rustls::{ClientConfig, RootCertStore, ServerConfig};
+use rustls::crypto::{ring, CryptoProvider};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::server::WebPkiClientVerifier;
+use tokio::io;
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+use super::Address;
+
+/// TLS settings for a `Server`: its own certificate and, for mutual TLS, the
+/// CA bundle client certificates must chain to.
+#[derive(Clone)]
+pub struct ServerTls {
+    acceptor: TlsAcceptor,
+    verifies_clients: bool,
+}
+
+impl ServerTls {
+    /// Loads the PEM certificate chain and private key. With `client_ca`,
+    /// clients must present a certificate signed by one of its CAs.
+    pub fn load<P: AsRef<Path>>(cert: P, key: P, client_ca: Option<P>) -> Result<Self, io::Error> {
+        let builder = ServerConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()
+            .map_err(invalid_input)?;
+
+        let builder = match &client_ca {
+            Some(client_ca) => {
+                let roots = Arc::new(load_roots(client_ca)?);
+                let verifier = WebPkiClientVerifier::builder_with_provider(roots, provider())
+                    .build()
+                    .map_err(invalid_input)?;
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+        let config = builder
+            .with_single_cert(load_certs(cert)?, load_key(key)?)
+            .map_err(invalid_input)?;
+
+        Ok(ServerTls {
+            acceptor: TlsAcceptor::from(Arc::new(config)),
+            verifies_clients: client_ca.is_some(),
+        })
+    }
+
+    pub(crate) fn acceptor(&self) -> &TlsAcceptor {
+        &self.acceptor
+    }
+}
+
+impl fmt::Debug for ServerTls {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServerTls").field("verifies_clients", &self.verifies_clients).finish()
+    }
+}
+
+/// TLS settings for a `Client`: the CAs the server certificate must chain to
+/// and, for mutual TLS, the client's own certificate.
+#[derive(Clone)]
+pub struct ClientTls {
+    connector: TlsConnector,
+    server_name: Option<String>,
+}
+
+impl ClientTls {
+    /// Trusts the CAs in the `ca` PEM bundle, or the Mozilla root store when
+    /// `None`. With `identity`, the given certificate chain and key are
+    /// presented to servers that ask for one.
+    pub fn load<P: AsRef<Path>>(ca: Option<P>, identity: Option<(P, P)>) -> Result<Self, io::Error> {
+        let roots = match ca {
+            Some(ca) => load_roots(ca)?,
+            None => RootCertStore { roots: webpki_roots::TLS_SERVER_ROOTS.to_vec() },
+        };
+        let builder = ClientConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()
+            .map_err(invalid_input)?
+            .with_root_certificates(roots);
+        let config = match identity {
+            Some((cert, key)) => builder.with_client_auth_cert(load_certs(cert)?, load_key(key)?).map_err(invalid_input)?,
+            None => builder.with_no_client_auth(),
+        };
+
+        Ok(ClientTls {
+            connector: TlsConnector::from(Arc::new(config)),
+            server_name: None,
+        })
+    }
+
+    /// Name the server certificate must be valid for. Defaults to the host
+    /// part of the address being dialled, or `localhost` for Unix sockets.
+    pub fn with_server_name(mut self, server_name: &str) -> Self {
+        self.server_name = Some(server_name.to_string());
+        self
+    }
+
+    pub(crate) fn connector(&self) -> &TlsConnector {
+        &self.connector
+    }
+
+    pub(crate) fn server_name(&self, addr: &str) -> Result<ServerName<'static>, io::Error> {
+        let name = match (&self.server_name, Address::parse(addr)) {
+            (Some(name), _) => name.as_str(),
+            (None, Address::Unix(_)) => "localhost",
+            (None, Address::Tcp(_)) => host(addr),
+        };
+        ServerName::try_from(name.to_string()).map_err(invalid_input)
+    }
+}
+
+impl fmt::Debug for ClientTls {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientTls").field("server_name", &self.server_name).finish()
+    }
+}
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(ring::default_provider())
+}
+
+/// Strips the port, and the brackets around IPv6 addresses.
+fn host(addr: &str) -> &str {
+    let host = addr.rsplit_once(':').map_or(addr, |(host, _)| host);
+    host.trim_start_matches('[').trim_end_matches(']')
+}
+
+fn load_certs<P: AsRef<Path>>(path: P) -> Result<Vec<CertificateDer<'static>>, io::Error> {
+    let mut reader = BufReader::new(File::open(&path)?);
+    let certs = rustls_pemfile::certs(&mut reader).collect::<Result<Vec<_>, _>>()?;
+    if certs.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("No certificate found in {}", path.as_ref().display())));
+    }
+    Ok(certs)
+}
+
+fn load_key<P: AsRef<Path>>(path: P) -> Result<PrivateKeyDer<'static>, io::Error> {
+    let mut reader = BufReader::new(File::open(&path)?);
+    rustls_pemfile::private_key(&mut reader)?
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("No private key found in {}", path.as_ref().display())))
+}
+
+fn load_roots<P: AsRef<Path>>(path: P) -> Result<RootCertStore, io::Error> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(path)? {
+        roots.add(cert).map_err(invalid_input)?;
+    }
+    Ok(roots)
+}
+
+fn invalid_input<E: std::error::Error + Send + Sync + 'static>(error: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, error)
+}
@@ -0,0 +1,74 @@
+#[cfg(test)]
+mod tests {
+    use connector::tcp::client::{Client, ClientOptions};
+    use connector::tcp::server::{Server, ServerOptions};
+    use connector::tcp::Authentication;
+    use connector::{Compression, Frame, FrameCodec, Message, Protocol};
+    use futures::{SinkExt, StreamExt};
+    use serde_json::json;
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::mpsc;
+    use tokio::time::{sleep, timeout, Duration};
+    use tokio_util::codec::Framed;
+
+    async fn start_server(authentication: Authentication) -> (String, mpsc::Receiver<Message>) {
+        let addr = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().to_string();
+        let (tx, rx) = mpsc::channel(10);
+        let options = ServerOptions { authentication: Some(authentication), ..ServerOptions::default() };
+        let server = Server::with_options(&addr, tx, options);
+        tokio::spawn(async move { server.start().await });
+        sleep(Duration::from_millis(100)).await;
+        (addr, rx)
+    }
+
+    /// Sends one message; the client keeps running while the returned
+    /// sender is alive.
+    async fn send(addr: &str, authentication: Option<Authentication>) -> mpsc::Sender<Message> {
+        let (tx, rx) = mpsc::channel(10);
+        Client::with_options(addr, rx, ClientOptions { authentication, ..ClientOptions::default() }).start().await;
+        tx.send(Message::new(Compression::None, Protocol::Json(json!({"authenticated": true})))).await.unwrap();
+        tx
+    }
+
+    #[tokio::test]
+    async fn test_token_authentication() {
+        let (addr, mut rx) = start_server(Authentication::Token("s3cret".to_string())).await;
+
+        let _client = send(&addr, Some(Authentication::Token("s3cret".to_string()))).await;
+
+        let received = timeout(Duration::from_secs(2), rx.recv()).await.unwrap().unwrap();
+        assert_eq!(received.payload.value(), &json!({"authenticated": true}));
+    }
+
+    #[tokio::test]
+    async fn test_hmac_authentication() {
+        let (addr, mut rx) = start_server(Authentication::Hmac(b"s3cret".to_vec())).await;
+
+        let _client = send(&addr, Some(Authentication::Hmac(b"s3cret".to_vec()))).await;
+
+        let received = timeout(Duration::from_secs(2), rx.recv()).await.unwrap().unwrap();
+        assert_eq!(received.payload.value(), &json!({"authenticated": true}));
+    }
+
+    #[tokio::test]
+    async fn test_wrong_secret_is_rejected() {
+        let (addr, mut rx) = start_server(Authentication::Hmac(b"s3cret".to_vec())).await;
+
+        let _client = send(&addr, Some(Authentication::Hmac(b"guess".to_vec()))).await;
+
+        assert!(timeout(Duration::from_secs(1), rx.recv()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unauthenticated_connection_is_closed() {
+        let (addr, mut rx) = start_server(Authentication::Token("s3cret".to_string())).await;
+
+        let mut framed = Framed::new(TcpStream::connect(&addr).await.unwrap(), FrameCodec::default());
+        assert!(matches!(framed.next().await, Some(Ok(Frame::Challenge(_)))));
+        framed.send(Frame::Message(Message::new(Compression::None, Protocol::Json(json!({}))))).await.unwrap();
+
+        let closed = timeout(Duration::from_secs(2), framed.next()).await.unwrap();
+        assert!(matches!(closed, None | Some(Err(_))));
+        assert!(rx.try_recv().is_err());
+    }
+}
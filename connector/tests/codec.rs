@@ -0,0 +1,314 @@
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+    use connector::{CodecError, Compression, CompressionPolicy, Envelope, Frame, FrameCodec, LegacyMessageCodec, Message, MessageBatch, MessageCodec, Protocol};
+    use serde_json::json;
+    use tokio_util::codec::{Decoder, Encoder};
+
+    fn round_trip(message: Message) -> Message {
+        let mut codec = MessageCodec::new().with_compression_policy(CompressionPolicy::always());
+        let mut buf = BytesMut::new();
+        codec.encode(message, &mut buf).unwrap();
+        let decoded = codec.decode(&mut buf).unwrap().unwrap();
+        assert!(buf.is_empty());
+        decoded
+    }
+
+    #[test]
+    fn test_msgpack_round_trip() {
+        let value = json!({"measurement": "cpu", "fields": {"usage": 0.42, "cores": 8}, "tags": ["a", "b"]});
+        let decoded = round_trip(Message::new(
+            Compression::None,
+            Protocol::MsgPack(value.clone()),
+        ));
+
+        assert!(matches!(decoded.payload, Protocol::MsgPack(_)));
+        assert_eq!(decoded.payload.value(), &value);
+    }
+
+    #[test]
+    fn test_msgpack_round_trip_with_zstd() {
+        let value = json!({"name": "John Doe", "age": 30, "active": true, "score": null});
+        let decoded = round_trip(Message::new(
+            Compression::Zstd(3),
+            Protocol::MsgPack(value.clone()),
+        ));
+
+        assert!(matches!(decoded.compression, Compression::Zstd(3)));
+        assert_eq!(decoded.payload.into_value(), value);
+    }
+
+    #[test]
+    fn test_msgpack_is_smaller_than_json() {
+        let value = json!({"measurement": "http_requests", "fields": {"count": 1234567, "ratio": 0.5}});
+        let json_len = Message::new(Compression::None, Protocol::Json(value.clone())).encode_data().unwrap().len();
+        let msgpack_len = Message::new(Compression::None, Protocol::MsgPack(value)).encode_data().unwrap().len();
+
+        assert!(msgpack_len < json_len);
+    }
+
+    #[test]
+    fn test_frame_carries_payload_once() {
+        let value = json!({"message": "Hello, World!"});
+        let message = Message::new(Compression::None, Protocol::Json(value.clone()));
+        let payload_len = message.encode_data().unwrap().len();
+        let envelope_len = rmp_serde::to_vec(&message.envelope).unwrap().len();
+
+        let mut buf = BytesMut::new();
+        MessageCodec::new().encode(message, &mut buf).unwrap();
+
+        assert_eq!(&buf[..4], b"MFLW");
+        assert_eq!(buf.len(), 24 + envelope_len + payload_len);
+        assert_eq!(String::from_utf8_lossy(&buf).matches("Hello, World!").count(), 1);
+    }
+
+    #[test]
+    fn test_partial_frame_waits_for_more_data() {
+        let mut encoded = BytesMut::new();
+        let mut codec = MessageCodec::new().with_compression_policy(CompressionPolicy::always());
+        codec.encode(Message::new(Compression::Zstd(1), Protocol::Json(json!({"a": 1}))), &mut encoded).unwrap();
+
+        let mut buf = BytesMut::new();
+        for byte in &encoded[..encoded.len() - 1] {
+            buf.extend_from_slice(&[*byte]);
+            assert!(codec.decode(&mut buf).unwrap().is_none());
+        }
+        buf.extend_from_slice(&encoded[encoded.len() - 1..]);
+
+        let decoded = codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(decoded.payload.value(), &json!({"a": 1}));
+    }
+
+    #[test]
+    fn test_decodes_legacy_frames() {
+        let mut buf = BytesMut::new();
+        LegacyMessageCodec.encode(Message::new(Compression::Zstd(3), Protocol::Json(json!({"legacy": true}))), &mut buf).unwrap();
+        MessageCodec::new().encode(Message::new(Compression::None, Protocol::MsgPack(json!({"legacy": false}))), &mut buf).unwrap();
+
+        let mut codec = MessageCodec::new();
+        let legacy = codec.decode(&mut buf).unwrap().unwrap();
+        let framed = codec.decode(&mut buf).unwrap().unwrap();
+
+        assert!(matches!(legacy.compression, Compression::Zstd(3)));
+        assert_eq!(legacy.payload.value(), &json!({"legacy": true}));
+        assert!(matches!(framed.payload, Protocol::MsgPack(_)));
+        assert_eq!(framed.payload.value(), &json!({"legacy": false}));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_round_trip_with_every_compression() {
+        let value = json!({"measurement": "cpu", "fields": {"usage": 0.42}, "tags": {"host": "server-01"}});
+
+        for compression in [Compression::Lz4, Compression::Gzip(6), Compression::Snappy] {
+            for payload in [Protocol::Json(value.clone()), Protocol::MsgPack(value.clone())] {
+                let message = Message::new(compression.clone(), payload);
+                let decoded = round_trip(message.clone());
+                assert_eq!(decoded.compression, compression);
+                assert_eq!(decoded.payload.value(), &value);
+
+                let encoded = message.encode_data().unwrap();
+                let data = Message::decode_data(&encoded, &message.compression, &message.payload).unwrap();
+                assert_eq!(data, value);
+            }
+        }
+    }
+
+    #[test]
+    fn test_decoded_payloads_are_bounded() {
+        let message = Message::new(Compression::Gzip(6), Protocol::Json(json!("a".repeat(4096))));
+        let encoded = message.encode_data().unwrap();
+
+        assert!(Message::decode_data(&encoded, &message.compression, &message.payload).is_ok());
+        let error = Message::decode_data_with_limit(&encoded, &message.compression, &message.payload, 1024).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+
+        let codec = MessageCodec::new().with_max_frame_size(1024);
+        assert!(codec.decode_data(&encoded, &message.compression, &message.payload).is_err());
+    }
+
+    #[test]
+    fn test_compression_shrinks_repetitive_payloads() {
+        let value = json!(vec![json!({"measurement": "http_requests", "bucket": "test"}); 50]);
+        let uncompressed = Message::new(Compression::None, Protocol::Json(value.clone())).encode_data().unwrap().len();
+
+        for compression in [Compression::Zstd(3), Compression::Lz4, Compression::Gzip(6), Compression::Snappy] {
+            let compressed = Message::new(compression, Protocol::Json(value.clone())).encode_data().unwrap().len();
+            assert!(compressed < uncompressed / 4);
+        }
+    }
+
+    fn encode_with(policy: CompressionPolicy, message: Message) -> (Message, usize) {
+        let mut codec = MessageCodec::new().with_compression_policy(policy);
+        let mut buf = BytesMut::new();
+        codec.encode(message, &mut buf).unwrap();
+        let len = buf.len();
+        (codec.decode(&mut buf).unwrap().unwrap(), len)
+    }
+
+    #[test]
+    fn test_small_payloads_are_sent_uncompressed() {
+        let value = json!({"message": "Hello, World!"});
+        let (decoded, _) = encode_with(
+            CompressionPolicy { min_size: 128, skip_if_larger: false },
+            Message::new(Compression::Zstd(3), Protocol::Json(value.clone())),
+        );
+
+        assert_eq!(decoded.compression, Compression::None);
+        assert_eq!(decoded.payload.value(), &value);
+    }
+
+    #[test]
+    fn test_payloads_growing_under_compression_are_sent_uncompressed() {
+        let value = json!({"a": 1});
+        let message = Message::new(Compression::Gzip(9), Protocol::Json(value.clone()));
+
+        let (forced, forced_len) = encode_with(CompressionPolicy::always(), message.clone());
+        let (decoded, len) = encode_with(CompressionPolicy { min_size: 0, skip_if_larger: true }, message);
+
+        assert_eq!(forced.compression, Compression::Gzip(9));
+        assert_eq!(decoded.compression, Compression::None);
+        assert!(len < forced_len);
+        assert_eq!(decoded.payload.value(), &value);
+    }
+
+    #[test]
+    fn test_large_payloads_are_compressed_by_default() {
+        let value = json!(vec![json!({"measurement": "http_requests", "bucket": "test"}); 50]);
+        let (decoded, _) = encode_with(
+            CompressionPolicy::default(),
+            Message::new(Compression::Lz4, Protocol::Json(value.clone())),
+        );
+
+        assert_eq!(decoded.compression, Compression::Lz4);
+        assert_eq!(decoded.payload.value(), &value);
+    }
+
+    #[test]
+    fn test_envelope_survives_framing() {
+        let envelope = Envelope::new().with_source("influx_source").with_header("trace", "abc123");
+        let message = Message {
+            envelope: envelope.clone(),
+            ..Message::new(Compression::Zstd(3), Protocol::MsgPack(json!({"a": 1})))
+        };
+
+        let decoded = round_trip(message);
+
+        assert_eq!(decoded.envelope, envelope);
+    }
+
+    #[test]
+    fn test_legacy_frames_without_envelope_get_a_fresh_one() {
+        let metadata = br#"{"compression":"None","payload":{"Json":null}}"#;
+        let payload = br#"{"legacy":true}"#;
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(&(metadata.len() as u64).to_le_bytes());
+        buf.extend_from_slice(metadata);
+        buf.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+        buf.extend_from_slice(payload);
+
+        let decoded = MessageCodec::new().decode(&mut buf).unwrap().unwrap();
+
+        assert_eq!(decoded.payload.value(), &json!({"legacy": true}));
+        assert_eq!(decoded.envelope.source, None);
+        assert_eq!(decoded.envelope.redeliveries, 0);
+    }
+
+    fn reading(i: u64) -> Message {
+        Message::new(Compression::None, Protocol::Json(json!({"measurement": "cpu", "tags": {"host": "web-1"}, "fields": {"usage": i}})))
+    }
+
+    #[test]
+    fn test_batch_is_unpacked_into_messages() {
+        let messages: Vec<Message> = (0..5).map(reading).collect();
+        let mut codec = MessageCodec::new();
+        let mut buf = BytesMut::new();
+        codec.encode(MessageBatch::new(Compression::Zstd(3), messages.clone()), &mut buf).unwrap();
+        codec.encode(reading(5), &mut buf).unwrap();
+
+        let mut decoded = Vec::new();
+        while let Some(message) = codec.decode(&mut buf).unwrap() {
+            decoded.push(message);
+        }
+
+        assert_eq!(decoded.len(), 6);
+        for (message, original) in decoded.iter().zip(messages.iter().chain([&reading(5)])) {
+            assert_eq!(message.payload.value(), original.payload.value());
+        }
+        assert_eq!(decoded[0].envelope, messages[0].envelope);
+        assert_eq!(decoded[0].compression, Compression::Zstd(3));
+    }
+
+    #[test]
+    fn test_empty_batch_is_refused() {
+        let mut codec = MessageCodec::new();
+        let mut buf = BytesMut::new();
+        codec.encode(MessageBatch::new(Compression::None, Vec::new()), &mut buf).unwrap();
+        codec.encode(reading(0), &mut buf).unwrap();
+
+        assert!(matches!(codec.decode(&mut buf), Err(CodecError::CorruptFrame(_))));
+    }
+
+    #[test]
+    fn test_batch_compresses_better_than_single_messages() {
+        let messages: Vec<Message> = (0..50).map(reading).collect();
+        let mut codec = MessageCodec::new().with_compression_policy(CompressionPolicy::always());
+
+        let mut batched = BytesMut::new();
+        codec.encode(MessageBatch::new(Compression::Zstd(3), messages.clone()), &mut batched).unwrap();
+        let mut single = BytesMut::new();
+        for message in messages {
+            codec.encode(Message { compression: Compression::Zstd(3), ..message }, &mut single).unwrap();
+        }
+
+        assert!(batched.len() < single.len() / 2);
+    }
+
+    #[test]
+    fn test_ack_and_nack_frames_round_trip() {
+        let message = reading(1);
+        let id = message.envelope.id;
+        let mut codec = FrameCodec::default();
+        let mut buf = BytesMut::new();
+        codec.encode(Frame::Ack(id), &mut buf).unwrap();
+        codec.encode(Frame::Nack { id, reason: "InfluxDB unavailable".to_string() }, &mut buf).unwrap();
+        codec.encode(Frame::Message(message), &mut buf).unwrap();
+
+        assert!(matches!(codec.decode(&mut buf).unwrap(), Some(Frame::Ack(acked)) if acked == id));
+        assert!(matches!(codec.decode(&mut buf).unwrap(), Some(Frame::Nack { id: nacked, reason }) if nacked == id && reason == "InfluxDB unavailable"));
+        assert!(matches!(codec.decode(&mut buf).unwrap(), Some(Frame::Message(decoded)) if decoded.envelope.id == id));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_challenge_and_auth_frames_round_trip() {
+        let mut codec = FrameCodec::default();
+        let mut buf = BytesMut::new();
+        codec.encode(Frame::Challenge(vec![7; 32]), &mut buf).unwrap();
+        codec.encode(Frame::Auth(b"proof".to_vec()), &mut buf).unwrap();
+
+        assert!(matches!(codec.decode(&mut buf).unwrap(), Some(Frame::Challenge(nonce)) if nonce == vec![7; 32]));
+        assert!(matches!(codec.decode(&mut buf).unwrap(), Some(Frame::Auth(proof)) if proof == b"proof"));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_ping_and_pong_frames_round_trip() {
+        let mut codec = FrameCodec::default();
+        let mut buf = BytesMut::new();
+        codec.encode(Frame::Ping, &mut buf).unwrap();
+        codec.encode(Frame::Pong, &mut buf).unwrap();
+
+        assert!(matches!(codec.decode(&mut buf).unwrap(), Some(Frame::Ping)));
+        assert!(matches!(codec.decode(&mut buf).unwrap(), Some(Frame::Pong)));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_message_codec_rejects_control_frames() {
+        let mut buf = BytesMut::new();
+        FrameCodec::default().encode(Frame::Ack(Envelope::new().id), &mut buf).unwrap();
+
+        assert!(matches!(MessageCodec::new().decode(&mut buf), Err(CodecError::UnexpectedFrame("ACK"))));
+    }
+}
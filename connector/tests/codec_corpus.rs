@@ -0,0 +1,184 @@
+#[cfg(test)]
+mod tests {
+    use bytes::{BufMut, BytesMut};
+    use connector::{CodecError, Compression, CompressionPolicy, Envelope, LegacyMessageCodec, Message, MessageBatch, MessageCodec, Protocol};
+    use serde_json::json;
+    use tokio_util::codec::{Decoder, Encoder};
+
+    const MAX_FRAME_SIZE: usize = 64 * 1024;
+
+    /// Small deterministic xorshift generator so the corpus is reproducible.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+    }
+
+    fn seeds() -> Vec<Vec<u8>> {
+        let payloads = [
+            json!({"message": "Hello, World!"}),
+            json!({"measurement": "cpu", "fields": {"usage": 0.5}, "tags": {"host": "a"}}),
+            json!([1, 2, 3, null, "x"]),
+        ];
+        let mut seeds = Vec::new();
+        for value in payloads {
+            for compression in [Compression::None, Compression::Zstd(3), Compression::Lz4, Compression::Gzip(6), Compression::Snappy] {
+                for payload in [Protocol::Json(value.clone()), Protocol::MsgPack(value.clone())] {
+                    let message = Message::new(compression.clone(), payload);
+                    let mut framed = BytesMut::new();
+                    MessageCodec::new().with_compression_policy(CompressionPolicy::always()).encode(message.clone(), &mut framed).unwrap();
+                    seeds.push(framed.to_vec());
+                    let mut legacy = BytesMut::new();
+                    LegacyMessageCodec.encode(message, &mut legacy).unwrap();
+                    seeds.push(legacy.to_vec());
+                }
+                let messages = vec![Message::new(Compression::None, Protocol::Json(value.clone())), Message::new(Compression::None, Protocol::MsgPack(value.clone()))];
+                let mut batch = BytesMut::new();
+                MessageCodec::new().with_compression_policy(CompressionPolicy::always()).encode(MessageBatch::new(compression.clone(), messages), &mut batch).unwrap();
+                seeds.push(batch.to_vec());
+            }
+        }
+        seeds
+    }
+
+    fn mutate(rng: &mut Rng, seed: &[u8]) -> Vec<u8> {
+        let mut input = seed.to_vec();
+        match rng.below(6) {
+            0 => input.truncate(rng.below(input.len() + 1)),
+            1 => {
+                for _ in 0..=rng.below(8) {
+                    let i = rng.below(input.len());
+                    input[i] ^= 1 << rng.below(8);
+                }
+            }
+            2 => {
+                let i = rng.below(input.len());
+                let len = (input.len() - i).min(8);
+                for byte in &mut input[i..i + len] {
+                    *byte = 0xff;
+                }
+            }
+            3 => {
+                let i = rng.below(input.len());
+                input[i] = rng.next() as u8;
+            }
+            4 => input = (0..rng.below(256)).map(|_| rng.next() as u8).collect(),
+            _ => {
+                let mut tail = mutate(rng, seed);
+                input.append(&mut tail);
+            }
+        }
+        input
+    }
+
+    /// Feeds `input` to a fresh codec until it stalls or fails.
+    fn drain(input: &[u8]) -> Result<usize, CodecError> {
+        let mut codec = MessageCodec::new().with_max_frame_size(MAX_FRAME_SIZE);
+        let mut buf = BytesMut::from(input);
+        let mut decoded = 0;
+        while let Some(_message) = codec.decode(&mut buf)? {
+            decoded += 1;
+        }
+        Ok(decoded)
+    }
+
+    /// Builds a JSON frame by hand, announcing `raw_len` uncompressed bytes.
+    fn frame(compression_tag: u8, level: u32, raw_len: u32, body: &[u8]) -> BytesMut {
+        let envelope = rmp_serde::to_vec(&Envelope::new()).unwrap();
+        let mut input = BytesMut::new();
+        input.extend_from_slice(b"MFLW");
+        input.put_u8(3);
+        input.put_u8(0);
+        input.put_u8(compression_tag);
+        input.put_u32_le(level);
+        input.put_u32_le(raw_len);
+        input.put_u32_le(body.len() as u32);
+        input.put_u32_le(envelope.len() as u32);
+        input.put_u8(0);
+        input.extend_from_slice(&envelope);
+        input.extend_from_slice(body);
+        input
+    }
+
+    #[test]
+    fn test_mutated_frames_never_panic() {
+        let seeds = seeds();
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+
+        for seed in &seeds {
+            assert!(drain(seed).unwrap() >= 1);
+        }
+
+        for _ in 0..20_000 {
+            let seed = &seeds[rng.below(seeds.len())];
+            let input = mutate(&mut rng, seed);
+            let _ = drain(&input);
+        }
+    }
+
+    #[test]
+    fn test_unsupported_version_is_rejected() {
+        let mut input = seeds()[0].clone();
+        input[4] = 99;
+
+        assert!(matches!(drain(&input), Err(CodecError::UnsupportedVersion(99))));
+    }
+
+    #[test]
+    fn test_unknown_tags_are_rejected() {
+        let mut input = seeds()[0].clone();
+        input[5] = 42;
+        assert!(matches!(drain(&input), Err(CodecError::UnknownProtocol(42))));
+
+        let mut input = seeds()[0].clone();
+        input[6] = 42;
+        assert!(matches!(drain(&input), Err(CodecError::UnknownCompression(42))));
+
+        let mut input = seeds()[0].clone();
+        input[23] = 42;
+        assert!(matches!(drain(&input), Err(CodecError::UnknownFrameKind(42))));
+    }
+
+    #[test]
+    fn test_oversized_frame_is_rejected_before_buffering() {
+        let mut input = seeds()[0][..24].to_vec();
+        input[15..19].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        assert!(matches!(drain(&input), Err(CodecError::FrameTooLarge { .. })));
+    }
+
+    #[test]
+    fn test_oversized_legacy_frame_is_rejected_before_buffering() {
+        let mut input = BytesMut::new();
+        input.put_u64_le(u64::MAX - 4);
+        input.put_u64_le(8);
+
+        assert!(matches!(drain(&input), Err(CodecError::FrameTooLarge { .. })));
+    }
+
+    #[test]
+    fn test_decompression_bomb_is_rejected() {
+        let raw = vec![b' '; MAX_FRAME_SIZE * 4];
+        let body = zstd::bulk::compress(&raw, 3).unwrap();
+        let input = frame(1, 3, 16, &body);
+
+        assert!(matches!(drain(&input), Err(CodecError::Decompression(_))));
+    }
+
+    #[test]
+    fn test_invalid_payload_is_a_decode_error() {
+        let body = b"{not json";
+        let input = frame(0, 0, body.len() as u32, body);
+
+        assert!(matches!(drain(&input), Err(CodecError::Deserialize(_))));
+    }
+}
@@ -0,0 +1,6 @@
+use connector::http::HttpRequest;
+
+/// A request to `path` on the mockito server.
+pub fn mock_request<T>(method: &str, path: &str) -> HttpRequest<T> {
+    HttpRequest::new(method, &format!("{}{}", mockito::server_url(), path))
+}
@@ -0,0 +1,103 @@
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+    use connector::{CodecError, Compression, CompressionPolicy, Message, MessageCodec, Protocol, ZstdDictionaries, ZstdDictionary};
+    use serde_json::json;
+    use tokio_util::codec::{Decoder, Encoder};
+
+    fn sample(i: u64) -> Message {
+        Message::new(
+            Compression::None,
+            Protocol::Json(json!({
+                "measurement": "http_requests",
+                "tags": {"host": format!("web-{}", i % 7), "region": "eu-west-1"},
+                "fields": {"count": i * 31, "latency_ms": i % 250},
+            })),
+        )
+    }
+
+    fn dictionaries() -> (u32, ZstdDictionaries) {
+        let samples: Vec<Message> = (0..1000).map(sample).collect();
+        let dictionary = ZstdDictionary::train(&samples, 4096).unwrap();
+        let id = dictionary.id();
+        let mut dictionaries = ZstdDictionaries::new();
+        dictionaries.insert(dictionary);
+        (id, dictionaries)
+    }
+
+    fn encode(codec: &mut MessageCodec, message: Message) -> Result<BytesMut, CodecError> {
+        let mut buf = BytesMut::new();
+        codec.encode(message, &mut buf)?;
+        Ok(buf)
+    }
+
+    #[test]
+    fn test_dictionary_round_trip() {
+        let (id, dictionaries) = dictionaries();
+        let mut codec = MessageCodec::new()
+            .with_compression_policy(CompressionPolicy::always())
+            .with_dictionaries(dictionaries);
+        let message = Message { compression: Compression::ZstdDict { level: 3, dictionary: id }, ..sample(4242) };
+
+        let mut buf = encode(&mut codec, message.clone()).unwrap();
+        let decoded = codec.decode(&mut buf).unwrap().unwrap();
+
+        assert_eq!(decoded.compression, Compression::ZstdDict { level: 3, dictionary: id });
+        assert_eq!(decoded.payload.value(), message.payload.value());
+    }
+
+    #[test]
+    fn test_dictionary_shrinks_small_messages() {
+        let (id, dictionaries) = dictionaries();
+        let mut codec = MessageCodec::new()
+            .with_compression_policy(CompressionPolicy::always())
+            .with_dictionaries(dictionaries);
+
+        let plain = encode(&mut codec, Message { compression: Compression::Zstd(3), ..sample(4242) }).unwrap();
+        let with_dictionary = encode(&mut codec, Message { compression: Compression::ZstdDict { level: 3, dictionary: id }, ..sample(4242) }).unwrap();
+
+        assert!(with_dictionary.len() < plain.len());
+    }
+
+    #[test]
+    fn test_unknown_dictionary_is_rejected() {
+        let (id, dictionaries) = dictionaries();
+        let message = Message { compression: Compression::ZstdDict { level: 3, dictionary: id }, ..sample(1) };
+
+        let mut sender = MessageCodec::new().with_compression_policy(CompressionPolicy::always());
+        assert!(matches!(encode(&mut sender, message.clone()), Err(CodecError::UnknownDictionary(unknown)) if unknown == id));
+
+        let mut sender = sender.with_dictionaries(dictionaries);
+        let mut buf = encode(&mut sender, message).unwrap();
+        let mut receiver = MessageCodec::new();
+        assert!(matches!(receiver.decode(&mut buf), Err(CodecError::UnknownDictionary(unknown)) if unknown == id));
+    }
+
+    #[test]
+    fn test_payload_encoding_needs_the_dictionaries() {
+        let (id, dictionaries) = dictionaries();
+        let message = Message { compression: Compression::ZstdDict { level: 3, dictionary: id }, ..sample(7) };
+
+        let error = message.encode_data().unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+
+        let codec = MessageCodec::new().with_dictionaries(dictionaries);
+        let encoded = codec.encode_data(&message).unwrap();
+        assert!(Message::decode_data(&encoded, &message.compression, &message.payload).is_err());
+        let decoded = codec.decode_data(&encoded, &message.compression, &message.payload).unwrap();
+        assert_eq!(&decoded, message.payload.value());
+    }
+
+    #[test]
+    fn test_dictionary_save_and_load() {
+        let samples: Vec<Message> = (0..1000).map(sample).collect();
+        let dictionary = ZstdDictionary::train(&samples, 4096).unwrap();
+        let path = std::env::temp_dir().join(format!("metaflow-dictionary-{}.zdict", std::process::id()));
+
+        dictionary.save(&path).unwrap();
+        let loaded = ZstdDictionaries::load(&[&path]).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.get(dictionary.id()).unwrap().data(), dictionary.data());
+    }
+}
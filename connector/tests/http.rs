@@ -1,9 +1,15 @@
+mod common;
+
 #[cfg(test)]
 mod tests {
-    use connector::http::{HttpClient, HttpClientError, HttpRequest};
+    use crate::common::mock_request;
+    use connector::http::{HttpAuth, HttpClient, HttpClientError, HttpRequest, HttpRetryPolicy, OAuth2ClientCredentials};
     use mockito::{mock, Matcher};
     use serde::{Deserialize, Serialize};
     use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
 
     #[derive(Debug, Deserialize)]
     struct TestResponse {
@@ -22,14 +28,7 @@ mod tests {
         let url = &mockito::server_url();
         let full_url = format!("{}/test", url);
 
-        let http_request = HttpRequest {
-            method: "GET".to_string(),
-            url: full_url,
-            body: None,
-            headers: None,
-            query_params: None,
-            timeout_duration: None,
-        };
+        let http_request = HttpRequest::new("GET", &full_url);
 
         let response = client.request::<(), serde_json::Value>(http_request).await.unwrap();
 
@@ -64,12 +63,9 @@ mod tests {
         };
 
         let http_request = HttpRequest {
-            method: "POST".to_string(),
-            url: full_url,
             body: Some(serde_json::to_value(post_body).unwrap()),
             headers: Some(HashMap::from([("Content-Type".to_string(), "application/json".to_string())])),
-            query_params: None,
-            timeout_duration: None,
+            ..HttpRequest::new("POST", &full_url)
         };
 
         let response = client.request(http_request).await.unwrap();
@@ -83,7 +79,7 @@ mod tests {
     async fn test_get_request_deserialize_error() {
         let _m = mock("GET", "/test")
             .with_status(200)
-            .with_header("content-type", "text/plain")
+            .with_header("content-type", "application/json")
             .with_body("This will cause a deserialize error")
             .create();
 
@@ -91,17 +87,165 @@ mod tests {
         let url = &mockito::server_url();
         let full_url = format!("{}/test", url);
 
-        let http_request = HttpRequest {
-            method: "GET".to_string(),
-            url: full_url,
-            body: None,
-            headers: None,
-            query_params: None,
-            timeout_duration: None,
-        };
+        let http_request = HttpRequest::new("GET", &full_url);
 
         let result = client.request::<(), serde_json::Value>(http_request).await;
 
         assert!(matches!(result, Err(HttpClientError::DeserializeError(_))));
     }
+
+    #[tokio::test]
+    async fn test_basic_and_bearer_auth() {
+        let basic = mock("GET", "/basic")
+            .match_header("Authorization", "Basic dXNlcjpzZWNyZXQ=")
+            .with_status(204)
+            .create();
+        let bearer = mock("GET", "/bearer")
+            .match_header("Authorization", "Bearer my-token")
+            .with_status(204)
+            .create();
+
+        let auth = HttpAuth::Basic { username: "user".to_string(), password: Some("secret".to_string()) };
+        HttpClient::new().with_auth(auth).request::<(), serde_json::Value>(mock_request("GET", "/basic")).await.unwrap();
+        let client = HttpClient::new().with_auth(HttpAuth::Bearer("my-token".to_string()));
+        let mut request = mock_request::<()>("GET", "/bearer");
+        request.headers = Some(HashMap::from([("Authorization".to_string(), "Bearer pasted".to_string())]));
+        client.request::<(), serde_json::Value>(request).await.unwrap();
+
+        basic.assert();
+        bearer.assert();
+    }
+
+    #[tokio::test]
+    async fn test_oauth2_token_is_cached_and_renewed_on_401() {
+        let issued = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&issued);
+        let token = mock("POST", "/token")
+            .match_body(Matcher::AllOf(vec![
+                Matcher::Regex("grant_type=client_credentials".to_string()),
+                Matcher::Regex("client_id=metaflow".to_string()),
+                Matcher::Regex("scope=read\\+write".to_string()),
+            ]))
+            .with_header("content-type", "application/json")
+            .with_body_from_fn(move |w| {
+                let n = counter.fetch_add(1, Ordering::SeqCst);
+                write!(w, r#"{{"access_token": "token-{}", "token_type": "Bearer", "expires_in": 3600}}"#, n)
+            })
+            .expect(2)
+            .create();
+        let revoked = mock("GET", "/data")
+            .match_header("Authorization", "Bearer token-0")
+            .with_status(401)
+            .expect(1)
+            .create();
+        let data = mock("GET", "/data")
+            .match_header("Authorization", "Bearer token-1")
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"message": "fresh"}"#)
+            .expect(2)
+            .create();
+
+        let client = HttpClient::new().with_auth(HttpAuth::OAuth2(OAuth2ClientCredentials {
+            token_url: format!("{}/token", mockito::server_url()),
+            client_id: "metaflow".to_string(),
+            client_secret: "secret".to_string(),
+            scopes: vec!["read".to_string(), "write".to_string()],
+        }));
+
+        // The first token is rejected, the second one is kept
+        for _ in 0..2 {
+            let response = client.request::<(), serde_json::Value>(mock_request("GET", "/data")).await.unwrap();
+            assert_eq!(response.body.unwrap()["message"], "fresh");
+        }
+
+        token.assert();
+        revoked.assert();
+        data.assert();
+    }
+
+    fn get_with_retries(path: &str) -> HttpRequest<()> {
+        HttpRequest {
+            retry: Some(HttpRetryPolicy {
+                initial_backoff: Duration::from_millis(10),
+                max_elapsed: Duration::from_secs(5),
+                ..HttpRetryPolicy::default()
+            }),
+            ..mock_request("GET", path)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_server_errors_are_retried() {
+        let unavailable = mock("GET", "/flaky").with_status(503).expect(2).create();
+        let recovered = mock("GET", "/flaky")
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"message": "recovered"}"#)
+            .expect(1)
+            .create();
+
+        let response = HttpClient::new().request::<(), serde_json::Value>(get_with_retries("/flaky")).await.unwrap();
+
+        assert_eq!(response.body.unwrap()["message"], "recovered");
+        unavailable.assert();
+        recovered.assert();
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_is_honored() {
+        let throttled = mock("GET", "/throttled").with_status(429).with_header("Retry-After", "1").expect(1).create();
+        let expired = mock("GET", "/throttled")
+            .with_status(503)
+            .with_header("Retry-After", "Wed, 21 Oct 2015 07:28:00 GMT")
+            .expect(1)
+            .create();
+        let served = mock("GET", "/throttled").with_status(204).expect(1).create();
+
+        let started = Instant::now();
+        HttpClient::new().request::<(), serde_json::Value>(get_with_retries("/throttled")).await.unwrap();
+
+        assert!(started.elapsed() >= Duration::from_secs(1));
+        assert!(started.elapsed() < Duration::from_secs(2));
+        throttled.assert();
+        expired.assert();
+        served.assert();
+    }
+
+    #[tokio::test]
+    async fn test_retries_stop_at_the_time_budget() {
+        let throttled = mock("GET", "/busy").with_status(429).with_header("Retry-After", "120").expect(1).create();
+        let served = mock("GET", "/busy").with_status(204).expect(0).create();
+
+        let started = Instant::now();
+        let result = HttpClient::new().request::<(), serde_json::Value>(get_with_retries("/busy")).await;
+
+        assert!(matches!(result, Err(HttpClientError::HttpError(_))));
+        assert!(started.elapsed() < Duration::from_secs(1));
+        throttled.assert();
+        served.assert();
+    }
+
+    #[tokio::test]
+    async fn test_client_errors_are_not_retried() {
+        let rejected = mock("GET", "/invalid").with_status(400).expect(1).create();
+        let served = mock("GET", "/invalid").with_status(204).expect(0).create();
+
+        let result = HttpClient::new().request::<(), serde_json::Value>(get_with_retries("/invalid")).await;
+
+        assert!(matches!(result, Err(HttpClientError::HttpError(_))));
+        rejected.assert();
+        served.assert();
+    }
+
+    #[tokio::test]
+    async fn test_connection_errors_are_retried_up_to_max_attempts() {
+        let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let request = HttpRequest { url: format!("http://{}/closed", addr), ..get_with_retries("/closed") };
+
+        let started = Instant::now();
+        let result = HttpClient::new().request::<(), serde_json::Value>(request).await;
+
+        assert!(matches!(result, Err(HttpClientError::RequestError(e)) if e.is_connect()));
+        // Two waits, of 10ms and then 20ms
+        assert!(started.elapsed() >= Duration::from_millis(30));
+    }
 }
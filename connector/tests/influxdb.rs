@@ -1,7 +1,9 @@
 #[cfg(test)]
 mod tests {
     use connector::influxdb::*;
+    use connector::{Compression, DataConnector, Message, Protocol};
     use mockito::{mock, server_url, Matcher};
+    use serde_json::json;
     use std::collections::HashMap;
     use std::time::SystemTime;
 
@@ -68,4 +70,46 @@ mod tests {
             panic!("Expected HttpClientError");
         }
     }
+
+    #[tokio::test]
+    async fn test_write_batch_sends_one_request_per_bucket() {
+        let cpu = mock("POST", "/api/v2/write")
+            .match_query(Matcher::UrlEncoded("bucket".to_string(), "cpu_bucket".to_string()))
+            .match_body(Matcher::Regex("^cpu.*\ncpu.*$".to_string()))
+            .with_status(204)
+            .expect(1)
+            .create();
+        let memory = mock("POST", "/api/v2/write")
+            .match_query(Matcher::UrlEncoded("bucket".to_string(), "memory_bucket".to_string()))
+            .match_body(Matcher::Regex("^memory[^\n]*$".to_string()))
+            .with_status(204)
+            .expect(1)
+            .create();
+
+        let client = InfluxDbClient::new(&server_url(), "my-token");
+        let point = |bucket: &str, measurement: &str| Message::new(
+            Compression::None,
+            Protocol::Json(json!({
+                "organization": "test_org",
+                "bucket": bucket,
+                "measurement": measurement,
+                "fields": {"value": 1},
+                "timestamp": 1700000000,
+            })),
+        );
+        let batch = vec![
+            point("cpu_bucket", "cpu"),
+            point("memory_bucket", "memory"),
+            point("cpu_bucket", "cpu"),
+            Message::new(Compression::None, Protocol::Json(json!({"measurement": "orphan"}))),
+        ];
+
+        let results = client.write_batch(batch).await;
+
+        cpu.assert();
+        memory.assert();
+        assert_eq!(results.len(), 4);
+        assert!(results[..3].iter().all(Result::is_ok));
+        assert!(results[3].is_err());
+    }
 }
@@ -0,0 +1,132 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::mock_request;
+    use connector::http::{HttpClient, HttpRequest, PageEmission, Pagination, PaginationStrategy};
+    use connector::{Compression, DataConnector, Message, Protocol};
+    use mockito::{mock, Matcher};
+    use serde_json::{json, Value};
+
+    fn query(path: &str, pagination: Pagination) -> Message {
+        let request = HttpRequest::<Value> { pagination: Some(pagination), ..mock_request("GET", path) };
+        Message::new(Compression::None, Protocol::Json(serde_json::to_value(request).unwrap()))
+    }
+
+    fn pagination(strategy: PaginationStrategy, items: Option<&str>, emit: PageEmission) -> Pagination {
+        Pagination { strategy, items: items.map(str::to_string), emit, max_pages: 100 }
+    }
+
+    fn bodies(messages: &[Message]) -> Vec<Value> {
+        messages.iter().map(|message| message.payload.value()["body"].clone()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_link_header_pages_are_merged() {
+        let first = mock("GET", "/link")
+            .match_query(Matcher::Missing)
+            .with_header("content-type", "application/json")
+            .with_header("Link", r#"</link?page=2>; rel="next", </link?page=9>; rel="last""#)
+            .with_body("[1, 2]")
+            .create();
+        let last = mock("GET", "/link")
+            .match_query(Matcher::UrlEncoded("page".to_string(), "2".to_string()))
+            .with_header("content-type", "application/json")
+            .with_body("[3]")
+            .create();
+
+        let strategy = pagination(PaginationStrategy::LinkHeader, None, PageEmission::Merged);
+        let messages = HttpClient::new().read_all(query("/link", strategy)).await.unwrap();
+
+        assert_eq!(bodies(&messages), vec![json!([1, 2, 3])]);
+        first.assert();
+        last.assert();
+    }
+
+    #[tokio::test]
+    async fn test_cursor_pages_are_emitted_one_by_one() {
+        let first = mock("GET", "/cursor")
+            .match_query(Matcher::Missing)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"data": [1, 2], "meta": {"next": "abc"}}"#)
+            .create();
+        let last = mock("GET", "/cursor")
+            .match_query(Matcher::UrlEncoded("cursor".to_string(), "abc".to_string()))
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"data": [3], "meta": {"next": null}}"#)
+            .create();
+
+        let strategy = PaginationStrategy::Cursor { cursor_path: "meta.next".to_string(), cursor_param: "cursor".to_string() };
+        let messages = HttpClient::new().read_all(query("/cursor", pagination(strategy, Some("data"), PageEmission::PerPage))).await.unwrap();
+
+        assert_eq!(messages.len(), 2);
+        assert_eq!(bodies(&messages)[1], json!({"data": [3], "meta": {"next": null}}));
+        assert_ne!(messages[0].envelope.id, messages[1].envelope.id);
+        first.assert();
+        last.assert();
+    }
+
+    #[tokio::test]
+    async fn test_offset_pages_stop_at_an_empty_page() {
+        let pages: Vec<_> = [(0, "[1, 2]"), (2, "[3, 4]"), (4, "[]")].into_iter()
+            .map(|(offset, body)| mock("GET", "/offset")
+                .match_query(Matcher::AllOf(vec![
+                    Matcher::UrlEncoded("offset".to_string(), offset.to_string()),
+                    Matcher::UrlEncoded("limit".to_string(), "2".to_string()),
+                ]))
+                .with_header("content-type", "application/json")
+                .with_body(body)
+                .expect(1)
+                .create())
+            .collect();
+
+        let strategy = PaginationStrategy::OffsetLimit {
+            offset_param: "offset".to_string(),
+            limit_param: "limit".to_string(),
+            limit: 2,
+            start: 0,
+        };
+        let messages = HttpClient::new().read_all(query("/offset", pagination(strategy, None, PageEmission::Merged))).await.unwrap();
+
+        assert_eq!(bodies(&messages), vec![json!([1, 2, 3, 4])]);
+        pages.iter().for_each(|page| page.assert());
+    }
+
+    #[tokio::test]
+    async fn test_page_numbers_stop_at_max_pages() {
+        let pages = mock("GET", "/numbered")
+            .match_query(Matcher::Regex("page=[1-3]$".to_string()))
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"items": ["more"]}"#)
+            .expect(3)
+            .create();
+
+        let strategy = PaginationStrategy::PageNumber { page_param: "page".to_string(), first_page: 1 };
+        let pagination = Pagination { max_pages: 3, ..pagination(strategy, Some("items"), PageEmission::PerPage) };
+        let messages = HttpClient::new().read_all(query("/numbered", pagination)).await.unwrap();
+
+        assert_eq!(messages.len(), 3);
+        pages.assert();
+    }
+
+    #[tokio::test]
+    async fn test_page_without_the_items_path_is_the_last() {
+        let pages = mock("GET", "/missing")
+            .match_query(Matcher::Any)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"error": "no such page"}"#)
+            .expect(1)
+            .create();
+
+        let strategy = PaginationStrategy::OffsetLimit {
+            offset_param: "offset".to_string(),
+            limit_param: "limit".to_string(),
+            limit: 2,
+            start: 0,
+        };
+        let messages = HttpClient::new().read_all(query("/missing", pagination(strategy, Some("data"), PageEmission::PerPage))).await.unwrap();
+
+        assert_eq!(messages.len(), 1);
+        pages.assert();
+    }
+}
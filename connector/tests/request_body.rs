@@ -0,0 +1,106 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::mock_request;
+    use connector::http::{EncodedBody, HttpClient, HttpClientError, HttpRequest, HttpResponse};
+    use mockito::{mock, Matcher};
+    use serde_json::{json, Value};
+    use std::collections::HashMap;
+
+    fn post(path: &str, content_type: &str, body: Value) -> HttpRequest<Value> {
+        HttpRequest {
+            body: Some(body),
+            headers: Some(HashMap::from([("Content-Type".to_string(), content_type.to_string())])),
+            ..mock_request("POST", path)
+        }
+    }
+
+    async fn send(client: &HttpClient, request: HttpRequest<Value>) -> Result<HttpResponse<Value>, HttpClientError> {
+        client.request::<Value, Value>(request).await
+    }
+
+    /// Posts `body` and checks the server received `expected`.
+    async fn assert_encoded(path: &str, content_type: &str, body: Value, expected: &str) {
+        let m = mock("POST", path)
+            .match_header("content-type", content_type)
+            .match_body(Matcher::Exact(expected.to_string()))
+            .with_status(204)
+            .create();
+
+        send(&HttpClient::new(), post(path, content_type, body)).await.unwrap();
+
+        m.assert();
+    }
+
+    #[tokio::test]
+    async fn test_text_is_sent_verbatim() {
+        assert_encoded("/text", "text/plain", json!("cpu value=1\nmem \"used\"=2"), "cpu value=1\nmem \"used\"=2").await;
+    }
+
+    #[tokio::test]
+    async fn test_ndjson_has_one_document_per_line() {
+        assert_encoded("/ndjson", "application/x-ndjson", json!([{"a": 1}, {"b": [2]}]), "{\"a\":1}\n{\"b\":[2]}\n").await;
+    }
+
+    #[tokio::test]
+    async fn test_csv_rows_share_a_header() {
+        let rows = json!([{"host": "web-1", "note": "a, \"b\""}, {"host": "web-2", "load": 0.5}]);
+        assert_encoded("/csv", "text/csv", rows, "host,note,load\r\nweb-1,\"a, \"\"b\"\"\",\r\nweb-2,,0.5\r\n").await;
+    }
+
+    #[tokio::test]
+    async fn test_xml_is_written_from_a_value_tree() {
+        let tree = json!({"hosts": {"@region": "eu", "host": ["web-1", "web-<2>"], "empty": null}});
+        let expected = "<?xml version=\"1.0\" encoding=\"UTF-8\"?><hosts region=\"eu\"><empty/><host>web-1</host><host>web-&lt;2&gt;</host></hosts>";
+        assert_encoded("/xml", "application/xml", tree, expected).await;
+    }
+
+    #[tokio::test]
+    async fn test_raw_bytes_are_decoded_from_base64() {
+        assert_encoded("/raw", "application/octet-stream", json!("aGk="), "hi").await;
+    }
+
+    #[tokio::test]
+    async fn test_multipart_form_has_a_part_per_field() {
+        let m = mock("POST", "/multipart")
+            .match_header("content-type", Matcher::Regex("^multipart/form-data; boundary=metaflow-\\w{24}$".to_string()))
+            .match_body(Matcher::AllOf(vec![
+                Matcher::Regex("Content-Disposition: form-data; name=\"title\"\r\n\r\nreport\r\n".to_string()),
+                Matcher::Regex("name=\"file\"; filename=\"a.csv\"\r\nContent-Type: text/csv\r\n\r\nx,y\r\n".to_string()),
+                Matcher::Regex("--metaflow-\\w{24}--\r\n$".to_string()),
+            ]))
+            .with_status(204)
+            .create();
+
+        let form = json!({"title": "report", "file": {"filename": "a.csv", "content_type": "text/csv", "content": "x,y"}});
+        send(&HttpClient::new(), post("/multipart", "multipart/form-data", form)).await.unwrap();
+
+        m.assert();
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_content_type_is_an_error() {
+        let result = send(&HttpClient::new(), post("/unsupported", "application/x-protobuf", json!({}))).await;
+
+        assert!(matches!(result, Err(HttpClientError::UnsupportedContentType(content_type)) if content_type == "application/x-protobuf"));
+    }
+
+    #[tokio::test]
+    async fn test_custom_encoder_can_be_plugged_in() {
+        let m = mock("POST", "/custom")
+            .match_header("content-type", "application/x-upper")
+            .match_body("HELLO")
+            .with_status(204)
+            .create();
+
+        let client = HttpClient::new().with_body_encoder("application/x-upper", |body: &Value, _: &str| {
+            body.as_str().map(|text| EncodedBody::new(text.to_uppercase())).ok_or_else(|| "expected a string".to_string())
+        });
+        send(&client, post("/custom", "application/x-upper", json!("hello"))).await.unwrap();
+        let result = send(&client, post("/custom", "application/x-upper", json!(1))).await;
+
+        m.assert();
+        assert!(matches!(result, Err(HttpClientError::EncodeError(_))));
+    }
+}
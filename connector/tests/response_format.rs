@@ -0,0 +1,97 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::mock_request;
+    use connector::http::{HttpClient, HttpRequest, ResponseFormat};
+    use mockito::mock;
+    use serde_json::{json, Value};
+
+    fn get(path: &str, response_format: Option<ResponseFormat>) -> HttpRequest<()> {
+        HttpRequest { response_format, ..mock_request("GET", path) }
+    }
+
+    async fn fetch(path: &str, content_type: &str, body: &str) -> Value {
+        let _m = mock("GET", path).with_header("content-type", content_type).with_body(body).create();
+        let response = HttpClient::new().request::<(), Value>(get(path, None)).await.unwrap();
+        assert_eq!(response.content_type.as_deref(), Some(content_type));
+        response.body.unwrap()
+    }
+
+    #[test]
+    fn test_format_follows_the_content_type() {
+        assert_eq!(ResponseFormat::from_content_type("application/json; charset=utf-8"), ResponseFormat::Json);
+        assert_eq!(ResponseFormat::from_content_type("application/vnd.api+json"), ResponseFormat::Json);
+        assert_eq!(ResponseFormat::from_content_type("text/csv"), ResponseFormat::Csv);
+        assert_eq!(ResponseFormat::from_content_type("application/atom+xml"), ResponseFormat::Xml);
+        assert_eq!(ResponseFormat::from_content_type("text/plain; version=0.0.4"), ResponseFormat::Prometheus);
+        assert_eq!(ResponseFormat::from_content_type("text/plain"), ResponseFormat::Text);
+        assert_eq!(ResponseFormat::from_content_type("application/octet-stream"), ResponseFormat::Raw);
+    }
+
+    #[tokio::test]
+    async fn test_csv_becomes_rows() {
+        let body = fetch("/csv", "text/csv", "host,note\r\nweb-1,\"says \"\"hi\"\", twice\"\r\nweb-2,\n").await;
+
+        assert_eq!(body, json!([{"host": "web-1", "note": "says \"hi\", twice"}, {"host": "web-2", "note": ""}]));
+    }
+
+    #[tokio::test]
+    async fn test_xml_becomes_a_value_tree() {
+        let xml = r#"<?xml version="1.0"?>
+            <!-- hosts -->
+            <hosts region="eu">
+                <host id="1">web-1</host>
+                <host id="2"><![CDATA[web-<2>]]></host>
+                <note>a &amp; b</note>
+                <empty/>
+            </hosts>"#;
+        let body = fetch("/xml", "application/xml", xml).await;
+
+        assert_eq!(body, json!({"hosts": {
+            "@region": "eu",
+            "host": [{"@id": "1", "#text": "web-1"}, {"@id": "2", "#text": "web-<2>"}],
+            "note": "a & b",
+            "empty": null,
+        }}));
+    }
+
+    #[tokio::test]
+    async fn test_deeply_nested_xml_is_refused() {
+        let xml = format!("{}{}", "<a>".repeat(10_000), "</a>".repeat(10_000));
+        let _m = mock("GET", "/nested").with_header("content-type", "application/xml").with_body(xml).create();
+
+        assert!(HttpClient::new().request::<(), Value>(get("/nested", None)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_prometheus_exposition_becomes_samples() {
+        let exposition = "# HELP http_requests_total Requests.\n\
+            # TYPE http_requests_total counter\n\
+            http_requests_total{method=\"post\",code=\"200\"} 1027 1395066363000\n\
+            temperature_celsius 21.5\n\
+            queue_depth{name=\"a\\\"b\"} NaN\n";
+        let body = fetch("/metrics", "text/plain; version=0.0.4", exposition).await;
+
+        assert_eq!(body, json!([
+            {"name": "http_requests_total", "labels": {"method": "post", "code": "200"}, "value": 1027.0, "timestamp": 1395066363000_i64},
+            {"name": "temperature_celsius", "labels": {}, "value": 21.5},
+            {"name": "queue_depth", "labels": {"name": "a\"b"}, "value": "NaN"},
+        ]));
+    }
+
+    #[tokio::test]
+    async fn test_text_and_raw_bodies() {
+        assert_eq!(fetch("/text", "text/plain", "plain words").await, json!("plain words"));
+        assert_eq!(fetch("/raw", "application/octet-stream", "\u{1}\u{2}").await, json!("AQI="));
+    }
+
+    #[tokio::test]
+    async fn test_configured_format_overrides_the_content_type() {
+        let _m = mock("GET", "/mislabeled").with_header("content-type", "text/plain").with_body(r#"{"ok": true}"#).create();
+
+        let response = HttpClient::new().request::<(), Value>(get("/mislabeled", Some(ResponseFormat::Json))).await.unwrap();
+
+        assert_eq!(response.body.unwrap(), json!({"ok": true}));
+    }
+}
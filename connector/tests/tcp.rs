@@ -0,0 +1,373 @@
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+    use connector::tcp::client::{BatchOptions, Client, ClientOptions};
+    use connector::tcp::server::{Server, ServerOptions};
+    use connector::tcp::{Acknowledgements, Address, ConnectionState, ReconnectPolicy, SpoolOptions};
+    use connector::{Compression, Frame, FrameCodec, Message, MessageCodec, Protocol};
+    use futures::{SinkExt, StreamExt};
+    use serde_json::json;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::mpsc;
+    use tokio::time::{sleep, timeout, Duration};
+    use tokio_util::codec::{Encoder, Framed};
+
+    async fn free_address() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        listener.local_addr().unwrap().to_string()
+    }
+
+    async fn start_server(addr: &str) -> mpsc::Receiver<Message> {
+        let (tx, rx) = mpsc::channel(10);
+        let server = Server::new(addr, tx);
+        tokio::spawn(async move { server.start().await });
+        sleep(Duration::from_millis(100)).await;
+        rx
+    }
+
+    async fn start_acknowledging_server(addr: &str, acknowledgements: &Acknowledgements) -> mpsc::Receiver<Message> {
+        let (tx, rx) = mpsc::channel(10);
+        let options = ServerOptions { acknowledgements: Some(acknowledgements.clone()), ..ServerOptions::default() };
+        let server = Server::with_options(addr, tx, options);
+        tokio::spawn(async move { server.start().await });
+        sleep(Duration::from_millis(100)).await;
+        rx
+    }
+
+    async fn start_client(addr: &str, ack_timeout: Duration) -> mpsc::Sender<Message> {
+        let (tx, rx) = mpsc::channel(10);
+        let options = ClientOptions { ack_timeout: Some(ack_timeout), ..ClientOptions::default() };
+        Client::with_options(addr, rx, options).start().await;
+        tx
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_frame_drops_only_that_connection() {
+        let addr = free_address().await;
+        let mut rx = start_server(&addr).await;
+
+        let mut bad = TcpStream::connect(&addr).await.unwrap();
+        bad.write_all(b"MFLW\x07garbage-after-an-unknown-version").await.unwrap();
+        let mut buf = [0u8; 16];
+        let closed = timeout(Duration::from_secs(2), bad.read(&mut buf)).await.unwrap();
+        assert!(matches!(closed, Ok(0) | Err(_)));
+
+        let socket = TcpStream::connect(&addr).await.unwrap();
+        let mut framed = Framed::new(socket, MessageCodec::new());
+        framed.send(Message::new(
+            Compression::None,
+            Protocol::Json(json!({"message": "Hello, World!"})),
+        )).await.unwrap();
+
+        let received = timeout(Duration::from_secs(2), rx.recv()).await.unwrap().unwrap();
+        assert_eq!(received.payload.value(), &json!({"message": "Hello, World!"}));
+    }
+
+    #[tokio::test]
+    async fn test_batched_client_delivers_every_message() {
+        let addr = free_address().await;
+        let mut rx = start_server(&addr).await;
+
+        let (tx, client_rx) = mpsc::channel(10);
+        let options = ClientOptions {
+            batch: Some(BatchOptions { max_messages: 4, linger: Duration::from_millis(20), ..BatchOptions::default() }),
+            ..ClientOptions::default()
+        };
+        Client::with_options(&addr, client_rx, options).start().await;
+
+        for i in 0..10 {
+            tx.send(Message::new(Compression::Zstd(3), Protocol::Json(json!({"i": i})))).await.unwrap();
+        }
+
+        let mut received = Vec::new();
+        for _ in 0..10 {
+            let message = timeout(Duration::from_secs(2), rx.recv()).await.unwrap().unwrap();
+            received.push(message.payload.value()["i"].as_i64().unwrap());
+        }
+        received.sort();
+        assert_eq!(received, (0..10).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn test_batches_stay_within_the_frame_limit() {
+        let addr = free_address().await;
+        let mut rx = start_server(&addr).await;
+
+        let (tx, client_rx) = mpsc::channel(10);
+        let options = ClientOptions {
+            max_frame_size: 1024,
+            batch: Some(BatchOptions { max_messages: 10, linger: Duration::from_millis(50), ..BatchOptions::default() }),
+            ..ClientOptions::default()
+        };
+        Client::with_options(&addr, client_rx, options).start().await;
+
+        for i in 0..10 {
+            tx.send(Message::new(Compression::None, Protocol::Json(json!({"i": i, "padding": "x".repeat(300)})))).await.unwrap();
+        }
+
+        let mut received = Vec::new();
+        for _ in 0..10 {
+            let message = timeout(Duration::from_secs(2), rx.recv()).await.unwrap().unwrap();
+            received.push(message.payload.value()["i"].as_i64().unwrap());
+        }
+        received.sort();
+        assert_eq!(received, (0..10).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn test_oversized_message_is_dropped_without_losing_the_others() {
+        let addr = free_address().await;
+        let mut rx = start_server(&addr).await;
+
+        let (tx, client_rx) = mpsc::channel(10);
+        let options = ClientOptions { max_frame_size: 1024, ack_timeout: None, ..ClientOptions::default() };
+        Client::with_options(&addr, client_rx, options).start().await;
+
+        tx.send(Message::new(Compression::None, Protocol::Json(json!({"big": "x".repeat(4096)})))).await.unwrap();
+        sleep(Duration::from_millis(100)).await;
+        tx.send(Message::new(Compression::None, Protocol::Json(json!({"small": 1})))).await.unwrap();
+
+        let received = timeout(Duration::from_secs(2), rx.recv()).await.unwrap().unwrap();
+        assert_eq!(received.payload.value(), &json!({"small": 1}));
+        assert!(timeout(Duration::from_millis(500), rx.recv()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unacknowledged_message_is_resent() {
+        let addr = free_address().await;
+        let acknowledgements = Acknowledgements::new();
+        let mut rx = start_acknowledging_server(&addr, &acknowledgements).await;
+        let tx = start_client(&addr, Duration::from_millis(200)).await;
+
+        tx.send(Message::new(Compression::None, Protocol::Json(json!({"a": 1})))).await.unwrap();
+
+        let first = timeout(Duration::from_secs(2), rx.recv()).await.unwrap().unwrap();
+        let second = timeout(Duration::from_secs(3), rx.recv()).await.unwrap().unwrap();
+        assert_eq!(second.envelope.id, first.envelope.id);
+        assert_eq!(second.envelope.redeliveries, first.envelope.redeliveries + 1);
+    }
+
+    #[tokio::test]
+    async fn test_nacked_message_is_resent_until_acknowledged() {
+        let addr = free_address().await;
+        let acknowledgements = Acknowledgements::new();
+        let mut rx = start_acknowledging_server(&addr, &acknowledgements).await;
+        let tx = start_client(&addr, Duration::from_secs(30)).await;
+
+        tx.send(Message::new(Compression::None, Protocol::Json(json!({"a": 1})))).await.unwrap();
+
+        let first = timeout(Duration::from_secs(2), rx.recv()).await.unwrap().unwrap();
+        assert!(acknowledgements.nack(&first.envelope.id, "InfluxDB unavailable"));
+
+        let second = timeout(Duration::from_secs(3), rx.recv()).await.unwrap().unwrap();
+        assert_eq!(second.envelope.id, first.envelope.id);
+        assert!(acknowledgements.ack(&second.envelope.id));
+
+        assert!(timeout(Duration::from_millis(1500), rx.recv()).await.is_err());
+    }
+
+    #[test]
+    fn test_address_parsing() {
+        assert_eq!(Address::parse("127.0.0.1:9000"), Address::Tcp("127.0.0.1:9000".to_string()));
+        assert_eq!(Address::parse("unix:///run/metaflow/sink.sock"), Address::Unix("/run/metaflow/sink.sock".into()));
+        assert_eq!(Address::parse("unix:///run/metaflow/sink.sock").to_string(), "unix:///run/metaflow/sink.sock");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_client_delivers_over_unix_socket() {
+        let path = std::env::temp_dir().join(format!("metaflow-{}.sock", std::process::id()));
+        let addr = format!("unix://{}", path.display());
+        // A stale socket file from an earlier run must not prevent binding
+        std::os::unix::net::UnixListener::bind(&path).unwrap();
+        let mut rx = start_server(&addr).await;
+
+        let (tx, client_rx) = mpsc::channel(10);
+        Client::new(&addr, client_rx).start().await;
+        tx.send(Message::new(Compression::None, Protocol::Json(json!({"local": true})))).await.unwrap();
+
+        let received = timeout(Duration::from_secs(2), rx.recv()).await.unwrap().unwrap();
+        assert_eq!(received.payload.value(), &json!({"local": true}));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_spooled_messages_survive_a_restart() {
+        let addr = free_address().await;
+        let dir = std::env::temp_dir().join(format!("metaflow-spool-{}", std::process::id()));
+        let (first_run, second_run) = (dir.join("first"), dir.join("second"));
+
+        // The sink is down: the first run spools everything it is given
+        let (tx, client_rx) = mpsc::channel(10);
+        let options = ClientOptions { spool: Some(SpoolOptions::new(&first_run)), ..ClientOptions::default() };
+        Client::with_options(&addr, client_rx, options).start().await;
+        for i in 0..3 {
+            tx.send(Message::new(Compression::None, Protocol::Json(json!({"i": i})))).await.unwrap();
+        }
+        sleep(Duration::from_millis(300)).await;
+
+        // Hand its spool over to a second run, as a restart would
+        std::fs::create_dir_all(&second_run).unwrap();
+        for entry in std::fs::read_dir(&first_run).unwrap() {
+            let entry = entry.unwrap();
+            std::fs::copy(entry.path(), second_run.join(entry.file_name())).unwrap();
+        }
+        std::fs::remove_dir_all(&first_run).unwrap();
+        let (_tx, client_rx) = mpsc::channel(10);
+        let options = ClientOptions { spool: Some(SpoolOptions::new(&second_run)), ..ClientOptions::default() };
+        Client::with_options(&addr, client_rx, options).start().await;
+
+        let mut rx = start_server(&addr).await;
+        let mut received = Vec::new();
+        for _ in 0..3 {
+            let message = timeout(Duration::from_secs(8), rx.recv()).await.unwrap().unwrap();
+            received.push(message.payload.value()["i"].as_i64().unwrap());
+        }
+        assert_eq!(received, vec![0, 1, 2]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_spool_is_kept_until_the_replay_is_acknowledged() {
+        let (down, up) = (free_address().await, free_address().await);
+        let dir = std::env::temp_dir().join(format!("metaflow-replay-{}", std::process::id()));
+        let (first_run, second_run) = (dir.join("first"), dir.join("second"));
+
+        let (tx, client_rx) = mpsc::channel(10);
+        let options = ClientOptions { spool: Some(SpoolOptions::new(&first_run)), ..ClientOptions::default() };
+        Client::with_options(&down, client_rx, options).start().await;
+        tx.send(Message::new(Compression::None, Protocol::Json(json!({"i": 0})))).await.unwrap();
+        sleep(Duration::from_millis(300)).await;
+
+        // The sink comes back and receives the replay, but never acknowledges it
+        let listener = TcpListener::bind(&down).await.unwrap();
+        let (socket, _) = timeout(Duration::from_secs(8), listener.accept()).await.unwrap().unwrap();
+        let mut framed = Framed::new(socket, FrameCodec::default());
+        let replayed = timeout(Duration::from_secs(2), framed.next()).await.unwrap().unwrap().unwrap();
+        assert!(matches!(replayed, Frame::Message(_)));
+
+        // Crashing now must not lose it: a restart finds it on disk
+        std::fs::create_dir_all(&second_run).unwrap();
+        for entry in std::fs::read_dir(&first_run).unwrap() {
+            let name = entry.unwrap().file_name().into_string().unwrap();
+            let renamed = name.replace(&down.replace(':', "_"), &up.replace(':', "_"));
+            std::fs::copy(first_run.join(&name), second_run.join(renamed)).unwrap();
+        }
+        let mut rx = start_server(&up).await;
+        let (_tx, client_rx) = mpsc::channel(10);
+        let options = ClientOptions { spool: Some(SpoolOptions::new(&second_run)), ..ClientOptions::default() };
+        Client::with_options(&up, client_rx, options).start().await;
+
+        let message = timeout(Duration::from_secs(2), rx.recv()).await.unwrap().unwrap();
+        assert_eq!(message.payload.value(), &json!({"i": 0}));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_unfinished_replay_survives_failed_connections() {
+        let addr = free_address().await;
+        let dir = std::env::temp_dir().join(format!("metaflow-unfinished-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // A crash during a replay left its file behind
+        let replay = dir.join(format!("{}.spool.replay", addr.replace(':', "_")));
+        let mut buf = BytesMut::new();
+        MessageCodec::new().encode(Message::new(Compression::None, Protocol::Json(json!({"i": 0}))), &mut buf).unwrap();
+        std::fs::write(&replay, &buf).unwrap();
+
+        let (_tx, client_rx) = mpsc::channel(10);
+        let reconnect = ReconnectPolicy { initial_delay: Duration::from_millis(50), jitter: 0.0, ..ReconnectPolicy::default() };
+        let options = ClientOptions { spool: Some(SpoolOptions::new(&dir)), reconnect, ..ClientOptions::default() };
+        Client::with_options(&addr, client_rx, options).start().await;
+        sleep(Duration::from_millis(300)).await;
+        assert!(replay.exists());
+
+        let mut rx = start_server(&addr).await;
+        let message = timeout(Duration::from_secs(8), rx.recv()).await.unwrap().unwrap();
+        assert_eq!(message.payload.value(), &json!({"i": 0}));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_reconnect_delay_backs_off_within_jitter() {
+        let policy = ReconnectPolicy {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+            multiplier: 2.0,
+            jitter: 0.1,
+            max_attempts: Some(5),
+        };
+
+        for (failures, expected) in [(1, 100), (2, 200), (3, 400), (4, 800), (10, 1000)] {
+            let delay = policy.delay(failures).as_millis() as f64;
+            assert!(delay >= expected as f64 * 0.9 && delay <= (expected as f64 * 1.1).min(1000.0), "{} failures: {}ms", failures, delay);
+        }
+        assert!(policy.should_retry(4));
+        assert!(!policy.should_retry(5));
+    }
+
+    #[tokio::test]
+    async fn test_client_reports_connection_state() {
+        let addr = free_address().await;
+        let (_tx, client_rx) = mpsc::channel(10);
+        let reconnect = ReconnectPolicy { initial_delay: Duration::from_millis(50), jitter: 0.0, max_attempts: Some(3), ..ReconnectPolicy::default() };
+        let client = Client::with_options(&addr, client_rx, ClientOptions { reconnect, ..ClientOptions::default() });
+        let mut state = client.state();
+        client.start().await;
+
+        // Nobody listens: the client gives up after three attempts
+        timeout(Duration::from_secs(2), state.wait_for(|state| *state == ConnectionState::Failed)).await.unwrap().unwrap();
+
+        let (_tx, client_rx) = mpsc::channel(10);
+        let _rx = start_server(&addr).await;
+        let client = Client::new(&addr, client_rx);
+        let mut state = client.state();
+        client.start().await;
+        timeout(Duration::from_secs(2), state.wait_for(|state| *state == ConnectionState::Connected)).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_server_answers_pings_and_closes_idle_connections() {
+        let addr = free_address().await;
+        let (tx, _rx) = mpsc::channel(10);
+        let options = ServerOptions { idle_timeout: Some(Duration::from_millis(300)), ..ServerOptions::default() };
+        let server = Server::with_options(&addr, tx, options);
+        tokio::spawn(async move { server.start().await });
+        sleep(Duration::from_millis(100)).await;
+
+        let mut framed = Framed::new(TcpStream::connect(&addr).await.unwrap(), FrameCodec::default());
+        framed.send(Frame::Ping).await.unwrap();
+        let pong = timeout(Duration::from_millis(200), framed.next()).await.unwrap();
+        assert!(matches!(pong, Some(Ok(Frame::Pong))));
+
+        let closed = timeout(Duration::from_secs(1), framed.next()).await.unwrap();
+        assert!(matches!(closed, None | Some(Err(_))));
+    }
+
+    #[tokio::test]
+    async fn test_client_reconnects_when_server_goes_silent() {
+        // Accepts connections but never answers, like a half-open peer
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let mut sockets = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                sockets.push(socket);
+            }
+        });
+
+        let (_tx, client_rx) = mpsc::channel(10);
+        let options = ClientOptions {
+            heartbeat_interval: Some(Duration::from_millis(100)),
+            idle_timeout: Some(Duration::from_millis(300)),
+            ..ClientOptions::default()
+        };
+        let client = Client::with_options(&addr, client_rx, options);
+        let mut state = client.state();
+        client.start().await;
+
+        timeout(Duration::from_secs(1), state.wait_for(|state| *state == ConnectionState::Connected)).await.unwrap().unwrap();
+        timeout(Duration::from_secs(1), state.wait_for(|state| *state == ConnectionState::Disconnected)).await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,111 @@
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use connector::tcp::client::{Client, ClientOptions};
+    use connector::tcp::server::{Server, ServerOptions};
+    use connector::tcp::{ClientTls, ServerTls};
+    use connector::{Compression, Message, Protocol};
+    use rcgen::{BasicConstraints, CertificateParams, ExtendedKeyUsagePurpose, IsCa, KeyPair};
+    use serde_json::json;
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+    use tokio::time::{sleep, timeout, Duration};
+
+    /// PEM files of a throwaway CA and the server and client certificates it
+    /// signed.
+    struct Pki {
+        dir: PathBuf,
+    }
+
+    impl Pki {
+        fn generate(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("metaflow-tls-{}-{}", name, std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+
+            let ca_key = KeyPair::generate().unwrap();
+            let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+            ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+            let ca = ca_params.self_signed(&ca_key).unwrap();
+            fs::write(dir.join("ca.pem"), ca.pem()).unwrap();
+
+            for (name, purpose) in [("server", ExtendedKeyUsagePurpose::ServerAuth), ("client", ExtendedKeyUsagePurpose::ClientAuth)] {
+                let key = KeyPair::generate().unwrap();
+                let mut params = CertificateParams::new(vec!["localhost".to_string(), "127.0.0.1".to_string()]).unwrap();
+                params.extended_key_usages = vec![purpose];
+                let cert = params.signed_by(&key, &ca, &ca_key).unwrap();
+                fs::write(dir.join(format!("{}.pem", name)), cert.pem()).unwrap();
+                fs::write(dir.join(format!("{}.key", name)), key.serialize_pem()).unwrap();
+            }
+
+            Pki { dir }
+        }
+
+        fn path(&self, file: &str) -> PathBuf {
+            self.dir.join(file)
+        }
+    }
+
+    impl Drop for Pki {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    async fn start_server(tls: ServerTls) -> (String, mpsc::Receiver<Message>) {
+        let addr = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().to_string();
+        let (tx, rx) = mpsc::channel(10);
+        let server = Server::with_options(&addr, tx, ServerOptions { tls: Some(tls), ..ServerOptions::default() });
+        tokio::spawn(async move { server.start().await });
+        sleep(Duration::from_millis(100)).await;
+        (addr, rx)
+    }
+
+    /// Sends one message; the client keeps running while the returned
+    /// sender is alive.
+    async fn send(addr: &str, tls: ClientTls) -> mpsc::Sender<Message> {
+        let (tx, rx) = mpsc::channel(10);
+        Client::with_options(addr, rx, ClientOptions { tls: Some(tls), ..ClientOptions::default() }).start().await;
+        tx.send(Message::new(Compression::None, Protocol::Json(json!({"secure": true})))).await.unwrap();
+        tx
+    }
+
+    #[tokio::test]
+    async fn test_message_is_delivered_over_tls() {
+        let pki = Pki::generate("plain");
+        let server_tls = ServerTls::load(pki.path("server.pem"), pki.path("server.key"), None).unwrap();
+        let (addr, mut rx) = start_server(server_tls).await;
+
+        let _client = send(&addr, ClientTls::load(Some(pki.path("ca.pem")), None).unwrap()).await;
+
+        let received = timeout(Duration::from_secs(2), rx.recv()).await.unwrap().unwrap();
+        assert_eq!(received.payload.value(), &json!({"secure": true}));
+    }
+
+    #[tokio::test]
+    async fn test_untrusted_server_is_refused() {
+        let pki = Pki::generate("untrusted");
+        let other = Pki::generate("other");
+        let server_tls = ServerTls::load(pki.path("server.pem"), pki.path("server.key"), None).unwrap();
+        let (addr, mut rx) = start_server(server_tls).await;
+
+        let _client = send(&addr, ClientTls::load(Some(other.path("ca.pem")), None).unwrap()).await;
+
+        assert!(timeout(Duration::from_secs(1), rx.recv()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_mutual_tls_requires_a_client_certificate() {
+        let pki = Pki::generate("mutual");
+        let server_tls = ServerTls::load(pki.path("server.pem"), pki.path("server.key"), Some(pki.path("ca.pem"))).unwrap();
+        let (addr, mut rx) = start_server(server_tls).await;
+
+        let _anonymous = send(&addr, ClientTls::load(Some(pki.path("ca.pem")), None).unwrap()).await;
+        assert!(timeout(Duration::from_secs(1), rx.recv()).await.is_err());
+
+        let identity = Some((pki.path("client.pem"), pki.path("client.key")));
+        let _client = send(&addr, ClientTls::load(Some(pki.path("ca.pem")), identity).unwrap()).await;
+        let received = timeout(Duration::from_secs(2), rx.recv()).await.unwrap().unwrap();
+        assert_eq!(received.payload.value(), &json!({"secure": true}));
+    }
+}
@@ -1,8 +1,17 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use tokio::sync::mpsc;
+use tokio::task::{JoinHandle, JoinSet};
+use tokio::time::{sleep, sleep_until, Duration, Instant};
 use tokio_util::sync::CancellationToken;
-use connector::{DataConnector, Message};
-use connector::tcp::server::Server;
+use uuid::Uuid;
+use connector::{DataConnector, DataConnectorError, Message};
+use connector::tcp::server::{Server, ServerOptions};
+use connector::tcp::Acknowledgements;
+use crate::dead_letter::{DeadLetter, DeadLetterQueue};
+use crate::retry::RetryPolicy;
 
 #[derive(Debug, thiserror::Error)]
 pub enum DataSinkError {
@@ -14,20 +23,123 @@ pub enum DataSinkError {
     DeserializeError(#[from] serde_json::Error),
 }
 
+#[derive(Debug, Clone)]
+pub struct DataSinkOptions {
+    pub server: ServerOptions,
+    /// How long `shutdown` waits for pending writes before dropping them.
+    pub shutdown_timeout: Duration,
+    /// Most writes through the connector at once. Further messages wait in
+    /// the queue, and once it is full the server stops reading from its
+    /// connections.
+    pub max_in_flight: usize,
+    /// Messages received but not yet being written.
+    pub queue_capacity: usize,
+    /// Hands messages to the connector in batches instead of one by one.
+    pub batch: Option<WriteBatchOptions>,
+    /// Retries failed writes; without a policy they fail at once.
+    pub retry: Option<RetryPolicy>,
+    /// Keeps the messages that failed for good. Without one they are
+    /// rejected, and the sender tries them again.
+    pub dead_letter: Option<DeadLetter>,
+}
+
+/// When to hand the messages collected for a batch to the connector.
+/// Whichever limit is reached first triggers the write.
+#[derive(Debug, Clone)]
+pub struct WriteBatchOptions {
+    pub max_messages: usize,
+    /// How long the first message of a batch may wait for others.
+    pub linger: Duration,
+}
+
+impl Default for WriteBatchOptions {
+    fn default() -> Self {
+        WriteBatchOptions {
+            max_messages: 100,
+            linger: Duration::from_millis(50),
+        }
+    }
+}
+
+impl Default for DataSinkOptions {
+    fn default() -> Self {
+        DataSinkOptions {
+            server: ServerOptions::default(),
+            shutdown_timeout: Duration::from_secs(30),
+            max_in_flight: 64,
+            queue_capacity: 100,
+            batch: None,
+            retry: None,
+            dead_letter: None,
+        }
+    }
+}
+
+/// What happened to the writes still pending when `shutdown` was called,
+/// or received while draining.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ShutdownSummary {
+    pub flushed: usize,
+    /// Rejected by the connector, and left to the sender to retry.
+    pub failed: usize,
+    /// Still pending at the deadline; the sender will resend them.
+    pub dropped: usize,
+}
+
+impl fmt::Display for ShutdownSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} flushed, {} failed, {} dropped", self.flushed, self.failed, self.dropped)
+    }
+}
+
+/// Load of a running `DataSink`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DataSinkStats {
+    /// Messages waiting for a write slot.
+    pub queue_depth: usize,
+    /// Messages being written.
+    pub in_flight: usize,
+}
+
 pub struct DataSink
 {
     connector: Arc<dyn DataConnector>,
     address: String,
+    options: DataSinkOptions,
     cancellation_token: CancellationToken,
+    server: Option<JoinHandle<()>>,
+    writer: Option<JoinHandle<ShutdownSummary>>,
+    /// Weak, so the queue still closes once the server is done with it.
+    queue: Option<mpsc::WeakSender<Message>>,
+    in_flight: Arc<AtomicUsize>,
 }
 
 impl DataSink
 {
     pub fn new(connector: Arc<dyn DataConnector>, address: &str) -> Self {
+        Self::with_options(connector, address, DataSinkOptions::default())
+    }
+
+    pub fn with_options(connector: Arc<dyn DataConnector>, address: &str, options: DataSinkOptions) -> Self {
         DataSink {
             connector,
             address: address.to_string(),
-            cancellation_token: CancellationToken::new()
+            options,
+            cancellation_token: CancellationToken::new(),
+            server: None,
+            writer: None,
+            queue: None,
+            in_flight: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn stats(&self) -> DataSinkStats {
+        let queue_depth = self.queue.as_ref()
+            .and_then(mpsc::WeakSender::upgrade)
+            .map_or(0, |queue| queue.max_capacity() - queue.capacity());
+        DataSinkStats {
+            queue_depth,
+            in_flight: self.in_flight.load(Ordering::Relaxed),
         }
     }
 
@@ -35,34 +147,220 @@ impl DataSink
         self.cancellation_token.cancel()
     }
 
+    /// Stops accepting connections, handles the messages already received
+    /// and waits up to `shutdown_timeout` for pending writes.
+    pub async fn shutdown(&mut self) -> ShutdownSummary {
+        self.stop();
+        if let Some(server) = self.server.take() {
+            let _ = server.await;
+        }
+        match self.writer.take() {
+            Some(writer) => writer.await.unwrap_or_default(),
+            None => ShutdownSummary::default(),
+        }
+    }
+
     pub async fn start(&mut self) -> Result<(), DataSinkError> {
-        let (tx, mut rx) = mpsc::channel::<Message>(100);
+        let (tx, rx) = mpsc::channel::<Message>(self.options.queue_capacity.max(1));
+        self.queue = Some(tx.downgrade());
         let addr = self.address.clone();
-        tokio::spawn(async move {
-            let server = Server::new(&addr, tx);
+        let acknowledgements = Acknowledgements::new();
+        let mut server_options = self.options.server.clone();
+        server_options.acknowledgements = Some(acknowledgements.clone());
+        let server = Server::with_options(&addr, tx, server_options)
+            .with_cancellation_token(self.cancellation_token.clone());
+        self.server = Some(tokio::spawn(async move {
             if let Err(e) = server.start().await {
                 eprintln!("Server failed: {:?}", e);
             }
+        }));
+
+        let writer = Arc::new(Writer {
+            connector: Arc::clone(&self.connector),
+            retry: self.options.retry.clone().unwrap_or_else(RetryPolicy::none),
+            dead_letter: self.options.dead_letter.clone().map(DeadLetterQueue::new),
         });
+        let cancellation_token = self.cancellation_token.clone();
+        let options = self.options.clone();
+        let in_flight = Arc::clone(&self.in_flight);
+        self.writer = Some(tokio::spawn(Self::write_messages(writer, rx, acknowledgements, cancellation_token, options, in_flight)));
 
-        let connector = Arc::clone(&self.connector);
+        Ok(())
+    }
 
-        tokio::spawn(async move {
-            while let Some(message) = rx.recv().await {
-                let connector = Arc::clone(&connector);
-                tokio::spawn(async move {
-                    match connector.write(message.clone()).await {
-                        Ok(response) => {
-                            println!("Processed message: {:?}", response);
-                        }
-                        Err(e) => {
-                            eprintln!("Error processing message: {:?}", e);
+    /// Writes each received message through the connector, at most
+    /// `max_in_flight` writes at a time, until shut down.
+    async fn write_messages(
+        writer: Arc<Writer>,
+        mut rx: mpsc::Receiver<Message>,
+        acknowledgements: Acknowledgements,
+        cancellation_token: CancellationToken,
+        options: DataSinkOptions,
+        in_flight: Arc<AtomicUsize>,
+    ) -> ShutdownSummary {
+        let max_in_flight = options.max_in_flight.max(1);
+        let mut writes = JoinSet::new();
+        let mut pending = HashSet::new();
+        let mut summary = ShutdownSummary::default();
+        let mut deadline = None;
+        let mut closed = false;
+        let mut batch = Vec::new();
+        let mut linger = None;
+
+        while !(closed && batch.is_empty() && writes.is_empty()) {
+            let saturated = writes.len() >= max_in_flight;
+            // Nothing is worth waiting for once no more messages will come
+            if (closed || deadline.is_some()) && !batch.is_empty() && !saturated {
+                Self::spawn_write(&mut writes, &writer, std::mem::take(&mut batch));
+                linger = None;
+                continue;
+            }
+            // Pending messages are either being written or waiting for a batch
+            in_flight.store(pending.len() - batch.len(), Ordering::Relaxed);
+
+            tokio::select! {
+                // Leaving messages in the queue while saturated holds back
+                // the server, and through it the senders
+                received = rx.recv(), if !closed && !saturated => match received {
+                    Some(message) => {
+                        pending.insert(message.envelope.id);
+                        match &options.batch {
+                            Some(batching) => {
+                                linger.get_or_insert_with(|| Instant::now() + batching.linger);
+                                batch.push(message);
+                                if batch.len() >= batching.max_messages {
+                                    Self::spawn_write(&mut writes, &writer, std::mem::take(&mut batch));
+                                    linger = None;
+                                }
+                            }
+                            None => Self::spawn_write(&mut writes, &writer, vec![message]),
                         }
                     }
-                });
+                    // The server has closed every connection
+                    None => closed = true,
+                },
+                _ = sleep_until(linger.unwrap_or_else(Instant::now)), if linger.is_some() && !saturated => {
+                    Self::spawn_write(&mut writes, &writer, std::mem::take(&mut batch));
+                    linger = None;
+                }
+                Some(joined) = writes.join_next() => {
+                    for (id, written) in joined.unwrap_or_default() {
+                        pending.remove(&id);
+                        Self::acknowledge(&acknowledgements, id, written, deadline.is_some(), &mut summary);
+                    }
+                }
+                _ = cancellation_token.cancelled(), if deadline.is_none() => {
+                    deadline = Some(Instant::now() + options.shutdown_timeout);
+                }
+                _ = sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => break,
             }
-        });
-        
-        Ok(())
+        }
+
+        writes.abort_all();
+        in_flight.store(0, Ordering::Relaxed);
+        rx.close();
+        while let Ok(message) = rx.try_recv() {
+            pending.insert(message.envelope.id);
+        }
+        for id in pending {
+            acknowledgements.nack(&id, "sink shutting down");
+            summary.dropped += 1;
+        }
+        summary
+    }
+
+    fn spawn_write(
+        writes: &mut JoinSet<Vec<(Uuid, Result<Message, DataConnectorError>)>>,
+        writer: &Arc<Writer>,
+        messages: Vec<Message>,
+    ) {
+        let writer = Arc::clone(writer);
+        writes.spawn(async move { writer.write(messages).await });
+    }
+
+    fn acknowledge(
+        acknowledgements: &Acknowledgements,
+        id: Uuid,
+        written: Result<Message, DataConnectorError>,
+        draining: bool,
+        summary: &mut ShutdownSummary,
+    ) {
+        match written {
+            Ok(response) => {
+                println!("Processed message: {:?}", response);
+                acknowledgements.ack(&id);
+                if draining {
+                    summary.flushed += 1;
+                }
+            }
+            Err(e) => {
+                eprintln!("Error processing message: {:?}", e);
+                acknowledgements.nack(&id, &e.to_string());
+                if draining {
+                    summary.failed += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Writes through the connector, retrying and dead-lettering as configured.
+struct Writer {
+    connector: Arc<dyn DataConnector>,
+    retry: RetryPolicy,
+    dead_letter: Option<DeadLetterQueue>,
+}
+
+impl Writer {
+    /// Writes `messages`, then again those that failed but may be retried.
+    async fn write(&self, mut messages: Vec<Message>) -> Vec<(Uuid, Result<Message, DataConnectorError>)> {
+        let mut outcomes = Vec::with_capacity(messages.len());
+        let mut attempts = 1;
+        while !messages.is_empty() {
+            let ids: Vec<Uuid> = messages.iter().map(|message| message.envelope.id).collect();
+            // Failed messages are needed again to retry or dead-letter them
+            let keep = attempts < self.retry.max_attempts || self.dead_letter.is_some();
+            let sent = if keep { messages.clone() } else { std::mem::take(&mut messages) };
+            let results = self.send(sent).await;
+
+            let mut kept = messages.into_iter();
+            let mut retries = Vec::new();
+            for (id, result) in ids.into_iter().zip(results) {
+                match (result, kept.next()) {
+                    (Err(e), Some(message)) if self.retry.should_retry(attempts, &e) => retries.push(message),
+                    (Err(e), Some(message)) => match &self.dead_letter {
+                        Some(dead_letter) => {
+                            eprintln!("Dead-lettering message {} after {} attempts: {}", id, attempts, e);
+                            outcomes.push((id, dead_letter.send(message, &e, attempts).await));
+                        }
+                        None => outcomes.push((id, Err(e))),
+                    },
+                    (result, _) => outcomes.push((id, result)),
+                }
+            }
+
+            if !retries.is_empty() {
+                sleep(self.retry.backoff(attempts)).await;
+            }
+            attempts += 1;
+            messages = retries;
+        }
+        outcomes
+    }
+
+    /// Writes alone through `write`, or together through `write_batch`.
+    /// A batch answered with the wrong number of results counts as failed
+    /// for the messages left without one.
+    async fn send(&self, mut messages: Vec<Message>) -> Vec<Result<Message, DataConnectorError>> {
+        let count = messages.len();
+        let mut results = match count {
+            1 => vec![self.connector.write(messages.remove(0)).await],
+            _ => self.connector.write_batch(messages).await,
+        };
+        if results.len() != count {
+            eprintln!("Connector returned {} results for a batch of {}", results.len(), count);
+            results.resize_with(count, || Err(DataConnectorError::OtherError("no result for this message".to_string())));
+        }
+        results
     }
 }
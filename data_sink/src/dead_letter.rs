@@ -0,0 +1,140 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+use serde::{Deserialize, Serialize};
+use tokio::fs::{self, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use connector::{DataConnector, DataConnectorError, Message};
+use crate::data_sink::DataSinkError;
+
+/// Where a `DataSink` puts messages it gave up writing, so they are kept
+/// instead of being bounced back to the sender forever.
+#[derive(Clone)]
+pub enum DeadLetter {
+    /// Appends a `DeadLetterRecord` per message to a JSON Lines file.
+    File(PathBuf),
+    /// Writes the messages through another connector.
+    Connector(Arc<dyn DataConnector>),
+}
+
+impl fmt::Debug for DeadLetter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeadLetter::File(path) => f.debug_tuple("File").field(path).finish(),
+            DeadLetter::Connector(_) => f.write_str("Connector"),
+        }
+    }
+}
+
+/// A dead-lettered message and why it was given up on.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetterRecord {
+    pub message: Message,
+    pub error: String,
+    pub attempts: u32,
+    pub failed_at: SystemTime,
+}
+
+/// Outcome of re-injecting a dead-letter file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReinjectSummary {
+    pub written: usize,
+    /// Failed again or could not be read, and kept in the file.
+    pub failed: usize,
+}
+
+impl fmt::Display for ReinjectSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} written, {} failed", self.written, self.failed)
+    }
+}
+
+/// Serializes appends, so concurrent writes never interleave their lines.
+pub(crate) struct DeadLetterQueue {
+    destination: DeadLetter,
+    lock: Mutex<()>,
+}
+
+impl DeadLetterQueue {
+    pub(crate) fn new(destination: DeadLetter) -> Self {
+        DeadLetterQueue { destination, lock: Mutex::new(()) }
+    }
+
+    pub(crate) async fn send(&self, message: Message, error: &DataConnectorError, attempts: u32) -> Result<Message, DataConnectorError> {
+        match &self.destination {
+            DeadLetter::File(path) => {
+                let record = DeadLetterRecord { message, error: error.to_string(), attempts, failed_at: SystemTime::now() };
+                let _guard = self.lock.lock().await;
+                append_records(path, std::slice::from_ref(&record)).await
+                    .map_err(|e| DataConnectorError::OtherError(format!("Dead-lettering to {} failed: {}", path.display(), e)))?;
+                Ok(record.message)
+            }
+            DeadLetter::Connector(connector) => connector.write(message).await,
+        }
+    }
+}
+
+async fn append_records(path: &Path, records: &[DeadLetterRecord]) -> Result<(), DataSinkError> {
+    let mut lines = Vec::with_capacity(records.len());
+    for record in records {
+        lines.push(serde_json::to_string(record)?);
+    }
+    append_lines(path, &lines).await
+}
+
+async fn append_lines(path: &Path, lines: &[String]) -> Result<(), DataSinkError> {
+    let mut content = String::new();
+    for line in lines {
+        content.push_str(line);
+        content.push('\n');
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path).await?;
+    file.write_all(content.as_bytes()).await?;
+    file.sync_data().await?;
+    Ok(())
+}
+
+/// Writes the messages of the dead-letter file at `path` through
+/// `connector`, once the cause of their failure is fixed. Messages failing
+/// again, and lines that cannot be read, stay in the file; the others are
+/// removed from it.
+///
+/// The file is first moved aside, so that a running `DataSink` can keep
+/// dead-lettering to `path` meanwhile. Should a run be interrupted, the
+/// next one picks up the moved file again.
+pub async fn reinject(path: &Path, connector: Arc<dyn DataConnector>) -> Result<ReinjectSummary, DataSinkError> {
+    let taken = path.with_extension("reinjecting");
+    if !fs::try_exists(&taken).await? {
+        fs::rename(path, &taken).await?;
+    }
+    let content = fs::read_to_string(&taken).await?;
+    let mut summary = ReinjectSummary::default();
+    let mut remaining = Vec::new();
+    for line in content.lines().filter(|line| !line.trim().is_empty()) {
+        let mut record: DeadLetterRecord = match serde_json::from_str(line) {
+            Ok(record) => record,
+            Err(e) => {
+                eprintln!("Keeping unreadable dead letter in {}: {}", path.display(), e);
+                remaining.push(line.to_string());
+                summary.failed += 1;
+                continue;
+            }
+        };
+        match connector.write(record.message.clone()).await {
+            Ok(_) => summary.written += 1,
+            Err(e) => {
+                record.error = e.to_string();
+                record.attempts += 1;
+                record.failed_at = SystemTime::now();
+                remaining.push(serde_json::to_string(&record)?);
+                summary.failed += 1;
+            }
+        }
+    }
+
+    append_lines(path, &remaining).await?;
+    fs::remove_file(&taken).await?;
+    Ok(summary)
+}
@@ -1 +1,3 @@
-pub mod data_sink;
\ No newline at end of file
+pub mod data_sink;
+pub mod dead_letter;
+pub mod retry;
@@ -0,0 +1,48 @@
+use std::collections::HashSet;
+use std::time::Duration;
+use connector::{DataConnectorError, ErrorKind};
+
+/// How a `DataSink` retries writes the connector rejected: up to
+/// `max_attempts` in all, waiting `initial_backoff` and then `multiplier`
+/// times longer after every failure, up to `max_backoff`.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub multiplier: f64,
+    /// Errors worth another attempt; others fail the write at once.
+    pub retry_on: HashSet<ErrorKind>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            multiplier: 2.0,
+            retry_on: HashSet::from([ErrorKind::Timeout, ErrorKind::Request, ErrorKind::Http]),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that gives up after the first attempt.
+    pub fn none() -> Self {
+        RetryPolicy { max_attempts: 1, ..RetryPolicy::default() }
+    }
+
+    /// Delay before the attempt following `attempts` failed ones.
+    pub fn backoff(&self, attempts: u32) -> Duration {
+        let exponent = attempts.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let backoff = self.initial_backoff.as_secs_f64() * self.multiplier.powi(exponent);
+        Duration::from_secs_f64(backoff.min(self.max_backoff.as_secs_f64()))
+    }
+
+    /// Whether a write that failed with `error` on attempt number
+    /// `attempts` is tried again.
+    pub fn should_retry(&self, attempts: u32, error: &DataConnectorError) -> bool {
+        attempts < self.max_attempts && self.retry_on.contains(&error.kind())
+    }
+}
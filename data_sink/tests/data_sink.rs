@@ -0,0 +1,182 @@
+use async_trait::async_trait;
+use connector::tcp::client::{Client, ClientOptions};
+use connector::{Compression, DataConnector, DataConnectorError, Message, Protocol};
+use data_sink::data_sink::{DataSink, DataSinkOptions, DataSinkStats, ShutdownSummary, WriteBatchOptions};
+use serde_json::json;
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio::time::{sleep, Duration};
+
+/// Takes `delay` to write each message.
+#[derive(Debug)]
+struct SlowConnector {
+    delay: Duration,
+}
+
+#[async_trait]
+impl DataConnector for SlowConnector {
+    async fn write(&self, data: Message) -> Result<Message, DataConnectorError> {
+        sleep(self.delay).await;
+        Ok(data)
+    }
+
+    async fn read(&self, _data: Message) -> Result<Message, DataConnectorError> {
+        unimplemented!()
+    }
+}
+
+/// Records the size of every batch it is handed.
+#[derive(Debug, Default)]
+struct BatchRecorder {
+    batches: Mutex<Vec<usize>>,
+}
+
+#[async_trait]
+impl DataConnector for BatchRecorder {
+    async fn write(&self, data: Message) -> Result<Message, DataConnectorError> {
+        self.batches.lock().unwrap().push(1);
+        Ok(data)
+    }
+
+    async fn read(&self, _data: Message) -> Result<Message, DataConnectorError> {
+        unimplemented!()
+    }
+
+    async fn write_batch(&self, data: Vec<Message>) -> Vec<Result<Message, DataConnectorError>> {
+        self.batches.lock().unwrap().push(data.len());
+        data.into_iter().map(Ok).collect()
+    }
+}
+
+/// Answers every batch with a result for its first message only.
+#[derive(Debug)]
+struct ShortBatchConnector;
+
+#[async_trait]
+impl DataConnector for ShortBatchConnector {
+    async fn write(&self, data: Message) -> Result<Message, DataConnectorError> {
+        Ok(data)
+    }
+
+    async fn read(&self, _data: Message) -> Result<Message, DataConnectorError> {
+        unimplemented!()
+    }
+
+    async fn write_batch(&self, data: Vec<Message>) -> Vec<Result<Message, DataConnectorError>> {
+        sleep(Duration::from_millis(300)).await;
+        data.into_iter().take(1).map(Ok).collect()
+    }
+}
+
+/// Starts a sink, sends it three messages and shuts it down while they are
+/// being written.
+async fn shut_down_while_writing(write_delay: Duration, shutdown_timeout: Duration) -> ShutdownSummary {
+    let addr = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().to_string();
+    let options = DataSinkOptions { shutdown_timeout, ..DataSinkOptions::default() };
+    let mut data_sink = DataSink::with_options(Arc::new(SlowConnector { delay: write_delay }), &addr, options);
+    data_sink.start().await.unwrap();
+    sleep(Duration::from_millis(100)).await;
+
+    let (tx, rx) = mpsc::channel(10);
+    Client::with_options(&addr, rx, ClientOptions::default()).start().await;
+    for i in 0..3 {
+        tx.send(Message::new(Compression::None, Protocol::Json(json!({"i": i})))).await.unwrap();
+    }
+    sleep(Duration::from_millis(100)).await;
+
+    tokio::time::timeout(Duration::from_secs(5), data_sink.shutdown()).await.unwrap()
+}
+
+#[tokio::test]
+async fn test_shutdown_waits_for_pending_writes() {
+    let summary = shut_down_while_writing(Duration::from_millis(300), Duration::from_secs(2)).await;
+
+    assert_eq!(summary, ShutdownSummary { flushed: 3, failed: 0, dropped: 0 });
+}
+
+#[tokio::test]
+async fn test_shutdown_drops_writes_past_the_deadline() {
+    let summary = shut_down_while_writing(Duration::from_secs(10), Duration::from_millis(200)).await;
+
+    assert_eq!(summary, ShutdownSummary { flushed: 0, failed: 0, dropped: 3 });
+}
+
+#[tokio::test]
+async fn test_writes_are_bounded_and_excess_is_queued() {
+    let addr = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().to_string();
+    let options = DataSinkOptions { max_in_flight: 2, queue_capacity: 3, ..DataSinkOptions::default() };
+    let mut data_sink = DataSink::with_options(Arc::new(SlowConnector { delay: Duration::from_secs(10) }), &addr, options);
+    data_sink.start().await.unwrap();
+    sleep(Duration::from_millis(100)).await;
+
+    let (tx, rx) = mpsc::channel(10);
+    Client::with_options(&addr, rx, ClientOptions::default()).start().await;
+    for i in 0..8 {
+        tx.send(Message::new(Compression::None, Protocol::Json(json!({"i": i})))).await.unwrap();
+    }
+    sleep(Duration::from_millis(300)).await;
+
+    assert_eq!(data_sink.stats(), DataSinkStats { queue_depth: 3, in_flight: 2 });
+}
+
+#[tokio::test]
+async fn test_writes_are_batched_by_size_and_linger() {
+    let addr = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().to_string();
+    let batch = WriteBatchOptions { max_messages: 4, linger: Duration::from_millis(200) };
+    let options = DataSinkOptions { batch: Some(batch), ..DataSinkOptions::default() };
+    let connector = Arc::new(BatchRecorder::default());
+    let mut data_sink = DataSink::with_options(connector.clone(), &addr, options);
+    data_sink.start().await.unwrap();
+    sleep(Duration::from_millis(100)).await;
+
+    let (tx, rx) = mpsc::channel(10);
+    Client::with_options(&addr, rx, ClientOptions::default()).start().await;
+    for i in 0..6 {
+        tx.send(Message::new(Compression::None, Protocol::Json(json!({"i": i})))).await.unwrap();
+    }
+    sleep(Duration::from_millis(100)).await;
+    assert_eq!(*connector.batches.lock().unwrap(), vec![4]);
+
+    sleep(Duration::from_millis(200)).await;
+    assert_eq!(*connector.batches.lock().unwrap(), vec![4, 2]);
+}
+
+#[tokio::test]
+async fn test_messages_missing_from_batch_results_fail() {
+    let addr = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().to_string();
+    let batch = WriteBatchOptions { max_messages: 2, linger: Duration::from_secs(10) };
+    let options = DataSinkOptions { batch: Some(batch), ..DataSinkOptions::default() };
+    let mut data_sink = DataSink::with_options(Arc::new(ShortBatchConnector), &addr, options);
+    data_sink.start().await.unwrap();
+    sleep(Duration::from_millis(100)).await;
+
+    let (tx, rx) = mpsc::channel(10);
+    Client::with_options(&addr, rx, ClientOptions::default()).start().await;
+    for i in 0..2 {
+        tx.send(Message::new(Compression::None, Protocol::Json(json!({"i": i})))).await.unwrap();
+    }
+    sleep(Duration::from_millis(100)).await;
+
+    let summary = tokio::time::timeout(Duration::from_secs(5), data_sink.shutdown()).await.unwrap();
+    assert_eq!(summary, ShutdownSummary { flushed: 1, failed: 1, dropped: 0 });
+}
+
+#[tokio::test]
+async fn test_in_flight_counts_the_messages_of_batched_writes() {
+    let addr = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().to_string();
+    let batch = WriteBatchOptions { max_messages: 4, linger: Duration::from_secs(10) };
+    let options = DataSinkOptions { batch: Some(batch), ..DataSinkOptions::default() };
+    let mut data_sink = DataSink::with_options(Arc::new(SlowConnector { delay: Duration::from_secs(10) }), &addr, options);
+    data_sink.start().await.unwrap();
+    sleep(Duration::from_millis(100)).await;
+
+    let (tx, rx) = mpsc::channel(10);
+    Client::with_options(&addr, rx, ClientOptions::default()).start().await;
+    for i in 0..6 {
+        tx.send(Message::new(Compression::None, Protocol::Json(json!({"i": i})))).await.unwrap();
+    }
+    sleep(Duration::from_millis(300)).await;
+
+    assert_eq!(data_sink.stats(), DataSinkStats { queue_depth: 0, in_flight: 4 });
+}
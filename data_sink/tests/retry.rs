@@ -0,0 +1,192 @@
+use async_trait::async_trait;
+use connector::http::HttpClientError;
+use connector::tcp::client::{Client, ClientOptions};
+use connector::influxdb::InfluxDbClient;
+use connector::{Compression, DataConnector, DataConnectorError, Message, Protocol};
+use data_sink::data_sink::{DataSink, DataSinkOptions, WriteBatchOptions};
+use data_sink::dead_letter::{reinject, DeadLetter, DeadLetterRecord, ReinjectSummary};
+use data_sink::retry::RetryPolicy;
+use serde_json::json;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use mockito::{mock, Matcher};
+use tokio::time::{sleep, Duration};
+
+/// Fails the first `failures` writes with `error`, then succeeds.
+struct FlakyConnector {
+    failures: u32,
+    error: fn() -> DataConnectorError,
+    attempts: AtomicU32,
+}
+
+impl FlakyConnector {
+    fn new(failures: u32, error: fn() -> DataConnectorError) -> Arc<Self> {
+        Arc::new(FlakyConnector { failures, error, attempts: AtomicU32::new(0) })
+    }
+}
+
+#[async_trait]
+impl DataConnector for FlakyConnector {
+    async fn write(&self, data: Message) -> Result<Message, DataConnectorError> {
+        if self.attempts.fetch_add(1, Ordering::SeqCst) < self.failures {
+            return Err((self.error)());
+        }
+        Ok(data)
+    }
+
+    async fn read(&self, _data: Message) -> Result<Message, DataConnectorError> {
+        unimplemented!()
+    }
+}
+
+fn server_error() -> DataConnectorError {
+    HttpClientError::HttpError("HTTP error: 503 Service Unavailable".to_string()).into()
+}
+
+fn invalid_payload() -> DataConnectorError {
+    DataConnectorError::OtherError("invalid payload".to_string())
+}
+
+fn quick_retries(max_attempts: u32) -> RetryPolicy {
+    RetryPolicy { max_attempts, initial_backoff: Duration::from_millis(10), ..RetryPolicy::default() }
+}
+
+/// Runs a sink writing through `connector` and sends it one message.
+async fn send_one(connector: Arc<FlakyConnector>, options: DataSinkOptions) -> (DataSink, mpsc::Sender<Message>) {
+    let addr = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().to_string();
+    let mut data_sink = DataSink::with_options(connector, &addr, options);
+    data_sink.start().await.unwrap();
+    sleep(Duration::from_millis(100)).await;
+
+    let (tx, rx) = mpsc::channel(10);
+    Client::with_options(&addr, rx, ClientOptions::default()).start().await;
+    tx.send(Message::new(Compression::None, Protocol::Json(json!({"value": 42})))).await.unwrap();
+    sleep(Duration::from_millis(300)).await;
+    (data_sink, tx)
+}
+
+#[test]
+fn test_backoff_grows_up_to_the_maximum() {
+    let policy = RetryPolicy { max_backoff: Duration::from_millis(300), ..RetryPolicy::default() };
+
+    assert_eq!(policy.backoff(1), Duration::from_millis(100));
+    assert_eq!(policy.backoff(2), Duration::from_millis(200));
+    assert_eq!(policy.backoff(3), Duration::from_millis(300));
+    assert!(policy.should_retry(2, &server_error()));
+    assert!(!policy.should_retry(3, &server_error()));
+    assert!(!policy.should_retry(1, &invalid_payload()));
+}
+
+#[tokio::test]
+async fn test_retryable_failures_are_retried() {
+    let connector = FlakyConnector::new(2, server_error);
+    let options = DataSinkOptions { retry: Some(quick_retries(3)), ..DataSinkOptions::default() };
+
+    let (mut data_sink, _tx) = send_one(connector.clone(), options).await;
+
+    assert_eq!(connector.attempts.load(Ordering::SeqCst), 3);
+    assert_eq!(data_sink.shutdown().await.failed, 0);
+}
+
+#[tokio::test]
+async fn test_failed_batches_are_retried() {
+    let unavailable = mock("POST", "/api/v2/write").match_query(Matcher::Any).with_status(503).expect(1).create();
+    let recovered = mock("POST", "/api/v2/write")
+        .match_query(Matcher::Any)
+        .match_body(Matcher::Regex("^cpu.*\ncpu.*$".to_string()))
+        .with_status(204)
+        .expect(1)
+        .create();
+    let connector = Arc::new(InfluxDbClient::new(&mockito::server_url(), "my-token"));
+    let options = DataSinkOptions {
+        batch: Some(WriteBatchOptions { max_messages: 2, linger: Duration::from_secs(10) }),
+        retry: Some(quick_retries(3)),
+        ..DataSinkOptions::default()
+    };
+    let addr = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().to_string();
+    let mut data_sink = DataSink::with_options(connector, &addr, options);
+    data_sink.start().await.unwrap();
+    sleep(Duration::from_millis(100)).await;
+
+    let (tx, rx) = mpsc::channel(10);
+    Client::with_options(&addr, rx, ClientOptions::default()).start().await;
+    for value in 0..2 {
+        let point = json!({"organization": "org", "bucket": "bucket", "measurement": "cpu", "fields": {"value": value}, "timestamp": 1700000000});
+        tx.send(Message::new(Compression::None, Protocol::Json(point))).await.unwrap();
+    }
+    sleep(Duration::from_millis(300)).await;
+
+    unavailable.assert();
+    recovered.assert();
+}
+
+#[tokio::test]
+async fn test_other_failures_are_not_retried() {
+    let connector = FlakyConnector::new(1, invalid_payload);
+    let dir = std::env::temp_dir().join(format!("metaflow-retry-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("not-retried.jsonl");
+    let options = DataSinkOptions {
+        retry: Some(quick_retries(3)),
+        dead_letter: Some(DeadLetter::File(path.clone())),
+        ..DataSinkOptions::default()
+    };
+
+    let (_data_sink, _tx) = send_one(connector.clone(), options).await;
+
+    assert_eq!(connector.attempts.load(Ordering::SeqCst), 1);
+    assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 1);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn test_exhausted_messages_are_dead_lettered_and_reinjected() {
+    let connector = FlakyConnector::new(u32::MAX, server_error);
+    let dir = std::env::temp_dir().join(format!("metaflow-retry-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("exhausted.jsonl");
+    let options = DataSinkOptions {
+        retry: Some(quick_retries(2)),
+        dead_letter: Some(DeadLetter::File(path.clone())),
+        ..DataSinkOptions::default()
+    };
+
+    let (_data_sink, _tx) = send_one(connector.clone(), options).await;
+
+    assert_eq!(connector.attempts.load(Ordering::SeqCst), 2);
+    let content = std::fs::read_to_string(&path).unwrap();
+    let record: DeadLetterRecord = serde_json::from_str(content.lines().next().unwrap()).unwrap();
+    assert_eq!(record.attempts, 2);
+    assert_eq!(record.message.payload.value(), &json!({"value": 42}));
+
+    let still_failing = reinject(&path, FlakyConnector::new(u32::MAX, server_error)).await.unwrap();
+    assert_eq!(still_failing, ReinjectSummary { written: 0, failed: 1 });
+    assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 1);
+
+    let fixed = reinject(&path, FlakyConnector::new(0, server_error)).await.unwrap();
+    assert_eq!(fixed, ReinjectSummary { written: 1, failed: 0 });
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn test_reinject_keeps_unreadable_lines() {
+    let dir = std::env::temp_dir().join(format!("metaflow-retry-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("unreadable.jsonl");
+    let record = DeadLetterRecord {
+        message: Message::new(Compression::None, Protocol::Json(json!({"value": 42}))),
+        error: "HTTP error: 503 Service Unavailable".to_string(),
+        attempts: 3,
+        failed_at: std::time::SystemTime::now(),
+    };
+    std::fs::write(&path, format!("{{not json\n{}\n", serde_json::to_string(&record).unwrap())).unwrap();
+
+    let summary = reinject(&path, FlakyConnector::new(0, server_error)).await.unwrap();
+
+    assert_eq!(summary, ReinjectSummary { written: 1, failed: 1 });
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "{not json\n");
+    std::fs::remove_file(&path).unwrap();
+}
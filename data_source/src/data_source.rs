@@ -1,12 +1,15 @@
 use tokio::time::{Duration, interval_at, Instant};
 use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
 use tokio_util::sync::CancellationToken;
 use log::{debug, error};
+use std::collections::HashMap;
 use std::fmt::Debug;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use crate::load_balancing::LoadBalancingStrategies;
 use crate::sender::DataSender;
 use connector::{DataConnector, Message, DataConnectorError};
+use connector::tcp::client::ClientOptions;
 
 #[derive(Debug, thiserror::Error)]
 pub enum DataSourceError {
@@ -25,7 +28,14 @@ pub struct DataSource {
     sinks: Option<Vec<String>>,
     load_balancing_strategy: Option<LoadBalancingStrategies>,
     transform: Option<Arc<dyn Fn(Message) -> Message + Send + Sync>>,
+    source_name: Option<String>,
+    client_options: ClientOptions,
+    sink_options: HashMap<String, ClientOptions>,
+    /// How long `shutdown` waits for the sinks to receive what was read.
+    shutdown_timeout: Duration,
     cancellation_token: CancellationToken,
+    /// Hands the messages read over to the sinks, once started.
+    sender_task: Mutex<Option<JoinHandle<()>>>,
 }
 
 impl DataSource {
@@ -44,7 +54,12 @@ impl DataSource {
             sinks,
             load_balancing_strategy,
             transform,
+            source_name: None,
+            client_options: ClientOptions::default(),
+            sink_options: HashMap::new(),
+            shutdown_timeout: Duration::from_secs(30),
             cancellation_token: CancellationToken::new(),
+            sender_task: Mutex::new(None),
         };
 
         debug!(
@@ -57,19 +72,67 @@ impl DataSource {
         data_source
     }
 
+    /// Names the source in the envelope of every message it produces.
+    pub fn with_source_name(mut self, source_name: &str) -> Self {
+        self.source_name = Some(source_name.to_string());
+        self
+    }
+
+    /// Sets the options used for the TCP clients connecting to the sinks.
+    pub fn with_client_options(mut self, client_options: ClientOptions) -> Self {
+        self.client_options = client_options;
+        self
+    }
+
+    /// Overrides the client options for the sink at `address`.
+    pub fn with_sink_options(mut self, address: &str, options: ClientOptions) -> Self {
+        self.sink_options.insert(address.to_string(), options);
+        self
+    }
+
+    /// Sets how long `shutdown` waits for the messages already read to reach
+    /// the sinks.
+    pub fn with_shutdown_timeout(mut self, shutdown_timeout: Duration) -> Self {
+        self.shutdown_timeout = shutdown_timeout;
+        self
+    }
+
     pub fn stop(&self) {
         self.cancellation_token.cancel()
     }
 
+    /// Stops polling and waits up to the shutdown timeout for the clients to
+    /// deliver the messages already read, returning whether they did. What
+    /// is left undelivered after that stays in the clients' spools, if they
+    /// have one, and is lost otherwise.
+    pub async fn shutdown(&self) -> bool {
+        self.stop();
+        let sender_task = self.sender_task.lock().unwrap().take();
+        match sender_task {
+            Some(sender_task) => tokio::time::timeout(self.shutdown_timeout, sender_task).await.is_ok(),
+            None => true,
+        }
+    }
+
     pub async fn start(&self) -> Result<(), DataSourceError> {
         let tx = if let Some(sinks) = &self.sinks {
             let (tx, rx) = mpsc::channel::<Message>(100);
-            let sinks = sinks.clone();
+            let endpoints = sinks.iter()
+                .map(|sink| {
+                    let options = self.sink_options.get(sink).unwrap_or(&self.client_options);
+                    (sink.clone(), options.clone())
+                })
+                .collect();
 
-            let mut data_sender = DataSender::new(sinks, self.load_balancing_strategy.clone(), rx);
-            tokio::spawn(async move {
+            let mut data_sender = DataSender::with_endpoints(
+                endpoints,
+                self.load_balancing_strategy.clone(),
+                rx,
+            );
+            let sender_task = tokio::spawn(async move {
                 data_sender.start().await;
             });
+            *self.sender_task.lock().unwrap() = Some(sender_task);
 
             Some(tx)
         } else {
@@ -99,19 +162,25 @@ impl DataSource {
         let connector = Arc::clone(&self.connector);
         let query = self.query.clone();
         let transform = self.transform.clone();
+        let source_name = self.source_name.clone();
         
         let handle = tokio::spawn(async move {
-            match connector.read(query).await {
-                Ok(mut response) => {
-                    if let Some(transform_fn) = transform {
-                        response = transform_fn(response);
-                    }
-                    if let Some(sender) = sender {
-                        if let Err(e) = sender.send(response).await {
-                            error!("Error sending to DataSender: {:?}", e);
+            match connector.read_all(query).await {
+                Ok(responses) => {
+                    for mut response in responses {
+                        if source_name.is_some() {
+                            response.envelope.source = source_name.clone();
+                        }
+                        if let Some(transform_fn) = &transform {
+                            response = transform_fn(response);
+                        }
+                        if let Some(sender) = &sender {
+                            if let Err(e) = sender.send(response).await {
+                                error!("Error sending to DataSender: {:?}", e);
+                            }
+                        } else {
+                            debug!("Sent response: {:?}", response);
                         }
-                    } else {
-                        debug!("Sent response: {:?}", response);
                     }
                 }
                 Err(e) => error!("Error querying endpoint: {:?}", e),
@@ -120,7 +189,7 @@ impl DataSource {
 
         handle.await.map_err(|e| {
             error!("Task join error: {:?}", e);
-            DataSourceError::TcpError(std::io::Error::new(std::io::ErrorKind::Other, e))
+            DataSourceError::TcpError(std::io::Error::other(e))
         })
     }
 }
@@ -56,4 +56,12 @@ impl LoadBalancingStrategy for LeastConnectionsLoadBalancingStrategy {
             self.decrement_connection(listener);
         }
     }
+
+    fn set_healthy(&mut self, listener: &str, healthy: bool) {
+        if healthy {
+            self.base.mark_ready(listener);
+        } else {
+            self.base.mark_busy(listener);
+        }
+    }
 }
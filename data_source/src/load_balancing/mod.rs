@@ -10,6 +10,9 @@ pub use strategies::{LoadBalancingStrategies, LoadBalancing};
 pub trait LoadBalancingStrategy {
     async fn select_listener(&mut self, listeners: &[String]) -> String;
     fn update_state(&mut self, listener: &str, busy: bool);
+    /// Excludes an unhealthy listener from selection, or brings it back,
+    /// without touching any per-listener accounting.
+    fn set_healthy(&mut self, listener: &str, healthy: bool);
 }
 
 #[derive(Clone)]
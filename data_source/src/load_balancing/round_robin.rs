@@ -44,4 +44,12 @@ impl LoadBalancingStrategy for RoundRobinLoadBalancingStrategy {
             self.base.mark_ready(listener);
         }
     }
+
+    fn set_healthy(&mut self, listener: &str, healthy: bool) {
+        if healthy {
+            self.base.mark_ready(listener);
+        } else {
+            self.base.mark_busy(listener);
+        }
+    }
 }
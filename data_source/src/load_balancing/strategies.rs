@@ -30,4 +30,10 @@ impl LoadBalancingStrategy for LoadBalancing {
             LoadBalancing::LeastConnections(strategy) => strategy.update_state(listener, busy),
         }
     }
+    fn set_healthy(&mut self, listener: &str, healthy: bool) {
+        match self {
+            LoadBalancing::RoundRobin(strategy) => strategy.set_healthy(listener, healthy),
+            LoadBalancing::LeastConnections(strategy) => strategy.set_healthy(listener, healthy),
+        }
+    }
 }
@@ -1,15 +1,21 @@
 use tokio::sync::mpsc;
 use tokio::task;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use crate::load_balancing::{LoadBalancing, LoadBalancingStrategies, LeastConnectionsLoadBalancingStrategy, RoundRobinLoadBalancingStrategy, LoadBalancingStrategy};
 use connector::Message;
-use connector::tcp::client::Client;
+use connector::tcp::client::{Client, ClientOptions};
+use connector::tcp::ConnectionState;
 
 pub struct DataSender {
     addresses: Vec<String>,
     load_balancing: LoadBalancing,
     client_senders: HashMap<String, mpsc::Sender<Message>>,
     receiver: mpsc::Receiver<Message>,
+    /// Connection state changes of every client, by address.
+    states: mpsc::UnboundedReceiver<(String, ConnectionState)>,
+    healthy: HashMap<String, bool>,
+    /// Whether each address is currently excluded from load balancing.
+    busy: HashMap<String, bool>,
 }
 
 impl DataSender {
@@ -18,6 +24,26 @@ impl DataSender {
         load_balancing_strategy: Option<LoadBalancingStrategies>,
         receiver: mpsc::Receiver<Message>
     ) -> Self {
+        Self::with_client_options(addresses, load_balancing_strategy, receiver, ClientOptions::default())
+    }
+
+    pub fn with_client_options(
+        addresses: Vec<String>,
+        load_balancing_strategy: Option<LoadBalancingStrategies>,
+        receiver: mpsc::Receiver<Message>,
+        client_options: ClientOptions,
+    ) -> Self {
+        let endpoints = addresses.into_iter().map(|addr| (addr, client_options.clone())).collect();
+        Self::with_endpoints(endpoints, load_balancing_strategy, receiver)
+    }
+
+    /// Like `with_client_options`, with separate options for each address.
+    pub fn with_endpoints(
+        endpoints: Vec<(String, ClientOptions)>,
+        load_balancing_strategy: Option<LoadBalancingStrategies>,
+        receiver: mpsc::Receiver<Message>,
+    ) -> Self {
+        let addresses: Vec<String> = endpoints.iter().map(|(addr, _)| addr.clone()).collect();
         let load_balancing = match load_balancing_strategy {
             None => LoadBalancing::LeastConnections(LeastConnectionsLoadBalancingStrategy::new(&addresses)),
             Some(strategy) => match strategy {
@@ -27,23 +53,69 @@ impl DataSender {
         };
 
         let mut client_senders = HashMap::new();
-        for addr in addresses.iter() {
+        let (states_tx, states) = mpsc::unbounded_channel();
+        for (addr, client_options) in endpoints {
             let (tx, rx) = mpsc::channel(100);
-            let client = Client::new(addr, rx);
+            let client = Client::with_options(&addr, rx, client_options);
+
+            let mut state = client.state();
+            let states_tx = states_tx.clone();
+            let state_addr = addr.clone();
+            task::spawn(async move {
+                loop {
+                    let current = *state.borrow_and_update();
+                    if states_tx.send((state_addr.clone(), current)).is_err() || state.changed().await.is_err() {
+                        break;
+                    }
+                }
+            });
+
             task::spawn(client.start());
-            client_senders.insert(addr.clone(), tx);
+            client_senders.insert(addr, tx);
         }
 
         Self {
+            healthy: addresses.iter().map(|addr| (addr.clone(), false)).collect(),
+            busy: addresses.iter().map(|addr| (addr.clone(), false)).collect(),
             addresses,
             load_balancing,
             client_senders,
-            receiver
+            receiver,
+            states,
+        }
+    }
+
+    /// Records a client's new state and excludes unhealthy sinks from load
+    /// balancing. While no sink is healthy, all of them stay eligible, so
+    /// messages keep reaching the clients' own buffers and spools.
+    fn update_health(&mut self, addr: &str, state: ConnectionState) {
+        if let Some(healthy) = self.healthy.get_mut(addr) {
+            *healthy = state.is_healthy();
+        }
+        let any_healthy = self.healthy.values().any(|healthy| *healthy);
+        for addr in &self.addresses {
+            let busy = any_healthy && !self.healthy[addr];
+            if self.busy.insert(addr.clone(), busy) != Some(busy) {
+                self.load_balancing.set_healthy(addr, !busy);
+            }
         }
     }
 
     pub async fn start(&mut self) {
-        while let Some(data) = self.receiver.recv().await {
+        loop {
+            // State changes go first, so no message is routed on stale health
+            let data = tokio::select! {
+                biased;
+                Some((addr, state)) = self.states.recv() => {
+                    self.update_health(&addr, state);
+                    continue;
+                }
+                data = self.receiver.recv() => match data {
+                    Some(data) => data,
+                    None => return self.close().await,
+                },
+            };
+
             let listener = self.load_balancing.select_listener(&self.addresses).await;
             if let Some(sender) = self.client_senders.get(&listener) {
                 if let Err(e) = sender.send(data).await {
@@ -54,4 +126,19 @@ impl DataSender {
             }
         }
     }
+
+    /// Hangs up on the clients and waits for them to deliver what they hold.
+    async fn close(&mut self) {
+        self.client_senders.clear();
+        let mut running: HashSet<String> = self.addresses.iter().cloned().collect();
+        while !running.is_empty() {
+            match self.states.recv().await {
+                Some((addr, state)) if state.is_stopped() => {
+                    running.remove(&addr);
+                }
+                Some(_) => {}
+                None => break,
+            }
+        }
+    }
 }
@@ -3,6 +3,8 @@ use connector::http::HttpRequest;
 use connector::{Message, Protocol, Compression};
 use connector::{DataConnector, DataConnectorError};
 use data_source::data_source::DataSource;
+use data_source::load_balancing::LoadBalancingStrategies;
+use data_source::sender::DataSender;
 use mockito::{mock, Matcher};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -37,15 +39,15 @@ impl DataConnector for MockConnector {
     }
 
     async fn read(&self, _data: Message) -> Result<Message, DataConnectorError> {
-        Ok(Message {
-            compression: Compression::None,
-            payload: Protocol::Json(serde_json::json!({
+        Ok(Message::new(
+            Compression::None,
+            Protocol::Json(serde_json::json!({
                 "status": 200,
                 "body": {
                     "message": "Hello, World!"
                 }
             })),
-        })
+        ))
     }
 }
 
@@ -72,21 +74,14 @@ async fn test_data_source_start_get_request_success() {
 
     let connector = Arc::new(MockConnector);
 
-    let query = HttpRequest::<()> {
-        method,
-        url,
-        body: None,
-        headers: None,
-        query_params: None,
-        timeout_duration: None,
-    };
+    let query = HttpRequest::<()>::new(&method, &url);
 
     let data_source = DataSource::new(
         connector,
-        Message {
-            compression: Compression::None,
-            payload: Protocol::Json(serde_json::to_value(query).unwrap()),
-        },
+        Message::new(
+            Compression::None,
+            Protocol::Json(serde_json::to_value(query).unwrap()),
+        ),
         Some(Duration::from_secs(1)),
         None,
         None,
@@ -123,20 +118,17 @@ async fn test_data_source_start_post_request_success() {
     let connector = Arc::new(MockConnector);
 
     let query = HttpRequest {
-        method,
-        url,
         body: Some(serde_json::to_value(post_body.clone()).unwrap()),
         headers,
-        query_params: None,
-        timeout_duration: None,
+        ..HttpRequest::new(&method, &url)
     };
 
     let data_source = DataSource::new(
         connector,
-        Message {
-            compression: Compression::None,
-            payload: Protocol::Json(serde_json::to_value(query).unwrap()),
-        },
+        Message::new(
+            Compression::None,
+            Protocol::Json(serde_json::to_value(query).unwrap()),
+        ),
         Some(Duration::from_secs(1)),
         None,
         None,
@@ -171,21 +163,14 @@ async fn test_data_source_with_tcp_server() {
 
     let connector = Arc::new(MockConnector);
 
-    let query = HttpRequest::<()> {
-        method,
-        url,
-        body: None,
-        headers: None,
-        query_params,
-        timeout_duration: None,
-    };
+    let query = HttpRequest::<()> { query_params, ..HttpRequest::new(&method, &url) };
 
     let data_source = DataSource::new(
         connector,
-        Message {
-            compression: Compression::None,
-            payload: Protocol::Json(serde_json::to_value(query).unwrap()),
-        },
+        Message::new(
+            Compression::None,
+            Protocol::Json(serde_json::to_value(query).unwrap()),
+        ),
         Some(Duration::from_secs(1)),
         Some(vec![local_addr.to_string()]),
         None,
@@ -225,21 +210,14 @@ async fn test_data_source_with_interval() {
 
     let connector = Arc::new(MockConnector);
 
-    let query = HttpRequest::<()> {
-        method,
-        url,
-        body: None,
-        headers: None,
-        query_params,
-        timeout_duration: None,
-    };
+    let query = HttpRequest::<()> { query_params, ..HttpRequest::new(&method, &url) };
 
     let data_source = DataSource::new(
         connector,
-        Message {
-            compression: Compression::None,
-            payload: Protocol::Json(serde_json::to_value(query).unwrap()),
-        },
+        Message::new(
+            Compression::None,
+            Protocol::Json(serde_json::to_value(query).unwrap()),
+        ),
         Some(Duration::from_secs(1)),
         None,
         None,
@@ -252,3 +230,64 @@ async fn test_data_source_with_interval() {
     tokio::time::sleep(Duration::from_secs(3)).await;
     data_source_handle.abort();
 }
+
+#[tokio::test]
+async fn test_data_sender_skips_unreachable_sinks() {
+    let (server_tx, mut server_rx) = tokio::sync::mpsc::channel(10);
+    let live = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().to_string();
+    let server = connector::tcp::server::Server::new(&live, server_tx);
+    tokio::spawn(async move { server.start().await });
+    // Nothing listens on the other sink
+    let dead = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().to_string();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let (tx, rx) = tokio::sync::mpsc::channel(10);
+    let mut sender = DataSender::new(vec![dead, live], Some(LoadBalancingStrategies::RoundRobin), rx);
+    tokio::spawn(async move { sender.start().await });
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    for i in 0..6 {
+        tx.send(Message::new(Compression::None, Protocol::Json(serde_json::json!({"i": i})))).await.unwrap();
+    }
+    for _ in 0..6 {
+        tokio::time::timeout(Duration::from_secs(2), server_rx.recv()).await.unwrap().unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_data_source_shutdown_waits_for_delivery() {
+    let (server_tx, mut server_rx) = tokio::sync::mpsc::channel(10);
+    let addr = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().to_string();
+    let server = connector::tcp::server::Server::new(&addr, server_tx);
+    tokio::spawn(async move { server.start().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let data_source = DataSource::new(
+        Arc::new(MockConnector),
+        Message::new(Compression::None, Protocol::Json(serde_json::json!({}))),
+        None,
+        Some(vec![addr]),
+        None,
+        None,
+    )
+    .with_shutdown_timeout(Duration::from_secs(5));
+    data_source.start().await.unwrap();
+
+    assert!(data_source.shutdown().await);
+    let received = server_rx.try_recv().unwrap();
+    let body: TestResponse = serde_json::from_value(received.payload.value()["body"].clone()).unwrap();
+    assert_eq!(body.message, "Hello, World!");
+}
+
+#[tokio::test]
+async fn test_health_changes_keep_least_connections_counts() {
+    use data_source::load_balancing::{LeastConnectionsLoadBalancingStrategy, LoadBalancingStrategy};
+
+    let listeners = vec!["a".to_string(), "b".to_string()];
+    let mut strategy = LeastConnectionsLoadBalancingStrategy::new(&listeners);
+    assert_eq!(strategy.select_listener(&listeners).await, "a");
+
+    strategy.set_healthy("a", false);
+    strategy.set_healthy("a", true);
+    assert_eq!(strategy.select_listener(&listeners).await, "b");
+}
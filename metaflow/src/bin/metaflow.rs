@@ -3,7 +3,7 @@ use std::env;
 use log::info;
 
 use metaflow::transformation::{TransformationConfig, get_value, register};
-use connector::{Message, Protocol};
+use connector::{Message, Protocol, ZstdDictionary, DEFAULT_DICTIONARY_SIZE};
 
 pub fn select(transformation_config: &TransformationConfig, message: Message) -> Message {
     info!("Original Message: {:?}", message);
@@ -12,13 +12,20 @@ pub fn select(transformation_config: &TransformationConfig, message: Message) ->
 
     let mut data = serde_json::Map::new();
     for (key, source_config) in &transformation_config.message.data {
-        let Protocol::Json(value) = get_value(source_config, &payload);
-        data.insert(key.clone(), value);
+        data.insert(key.clone(), get_value(source_config, &payload, &message.envelope).into_value());
     }
 
+    let data = serde_json::Value::Object(data);
+    let payload = match transformation_config.message.protocol.as_deref() {
+        Some("json") => Protocol::Json(data),
+        Some("msgpack") => Protocol::MsgPack(data),
+        _ => payload.with_value(data),
+    };
+
     let transformed_message = Message {
-        compression: message.compression,
-        payload: Protocol::Json(serde_json::Value::Object(data)),
+        compression: transformation_config.message.compression.clone().unwrap_or(message.compression),
+        payload,
+        envelope: message.envelope,
     };
 
     info!("Transformed Message: {:?}", transformed_message.payload);
@@ -31,8 +38,18 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     env_logger::init();
     register("select", select);
     let args: Vec<String> = env::args().collect();
+
+    if args.get(1).map(String::as_str) == Some("train-dictionary") {
+        return train_dictionary(&args);
+    }
+    if args.get(1).map(String::as_str) == Some("reinject") {
+        return reinject(&args).await;
+    }
+
     if args.len() != 2 {
         eprintln!("Usage: {} <config_file>", args[0]);
+        eprintln!("       {} train-dictionary <samples.jsonl> <output> [max_size]", args[0]);
+        eprintln!("       {} reinject <config_file> <data_sink> <dead_letters.jsonl>", args[0]);
         std::process::exit(1);
     }
     let config_path = &args[1];
@@ -41,7 +58,66 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let metaflow = MetaFlow::new(config_path)?;
 
-    metaflow.start().await?;
+    metaflow.run(shutdown_signal()).await?;
 
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Completes on SIGTERM, as sent by container runtimes, or Ctrl-C.
+#[cfg(unix)]
+async fn shutdown_signal() {
+    let mut terminate = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+        Ok(terminate) => terminate,
+        Err(e) => {
+            eprintln!("Failed to listen for SIGTERM: {}", e);
+            let _ = tokio::signal::ctrl_c().await;
+            return;
+        }
+    };
+    tokio::select! {
+        _ = terminate.recv() => info!("Received SIGTERM"),
+        _ = tokio::signal::ctrl_c() => info!("Received Ctrl-C"),
+    }
+}
+
+/// Completes on Ctrl-C; there is no SIGTERM outside Unix.
+#[cfg(not(unix))]
+async fn shutdown_signal() {
+    if tokio::signal::ctrl_c().await.is_ok() {
+        info!("Received Ctrl-C");
+    }
+}
+
+/// Trains a zstd dictionary from recorded messages, one JSON-serialized
+/// `Message` per line, and writes it to `output`.
+fn train_dictionary(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    if args.len() < 4 || args.len() > 5 {
+        eprintln!("Usage: {} train-dictionary <samples.jsonl> <output> [max_size]", args[0]);
+        std::process::exit(1);
+    }
+    let max_size = match args.get(4) {
+        Some(max_size) => max_size.parse()?,
+        None => DEFAULT_DICTIONARY_SIZE,
+    };
+
+    let dictionary = ZstdDictionary::train_from_file(&args[2], max_size)?;
+    dictionary.save(&args[3])?;
+    println!("Wrote zstd dictionary {} to {}", dictionary.id(), &args[3]);
+
+    Ok(())
+}
+
+/// Writes dead-lettered messages through the connector of a configured
+/// data sink, keeping those that fail again in the file.
+async fn reinject(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    if args.len() != 5 {
+        eprintln!("Usage: {} reinject <config_file> <data_sink> <dead_letters.jsonl>", args[0]);
+        std::process::exit(1);
+    }
+
+    let metaflow = MetaFlow::new(&args[2])?;
+    let summary = metaflow.reinject(&args[3], args[4].as_ref()).await?;
+    println!("Re-injected {}: {}", &args[4], summary);
+
+    Ok(())
+}
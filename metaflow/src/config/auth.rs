@@ -0,0 +1,33 @@
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use connector::tcp::Authentication;
+
+/// Shared secret a sink requires of the sources connecting to it.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AuthConfig {
+    /// Sent as is; only use over TLS.
+    Token { token: String },
+    /// Proven through an HMAC-SHA256 challenge-response, without the secret
+    /// crossing the wire.
+    Hmac { secret: String },
+}
+
+impl AuthConfig {
+    pub fn authentication(&self) -> Authentication {
+        match self {
+            AuthConfig::Token { token } => Authentication::Token(token.clone()),
+            AuthConfig::Hmac { secret } => Authentication::Hmac(secret.as_bytes().to_vec()),
+        }
+    }
+}
+
+/// Leaves the secret out, as configs end up in debug logs.
+impl fmt::Debug for AuthConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthConfig::Token { .. } => f.debug_struct("Token").field("token", &"<redacted>").finish(),
+            AuthConfig::Hmac { .. } => f.debug_struct("Hmac").field("secret", &"<redacted>").finish(),
+        }
+    }
+}
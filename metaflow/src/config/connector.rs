@@ -1,21 +1,83 @@
 use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::sync::Arc;
 use connector::DataConnector;
-use connector::http::HttpClient;
+use connector::http::{HttpAuth, HttpClient, OAuth2ClientCredentials};
 use connector::influxdb::InfluxDbClient;
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum ConnectorConfig {
-    HttpClient,
+    HttpClient {
+        auth: Option<HttpAuthConfig>,
+    },
     InfluxDbClient { url: String, token: String },
 }
 
 impl ConnectorConfig {
     pub fn create_connector(&self) -> Arc<dyn DataConnector> {
         match self {
-            ConnectorConfig::HttpClient => Arc::new(HttpClient::new()),
+            ConnectorConfig::HttpClient { auth } => {
+                let client = HttpClient::new();
+                match auth {
+                    Some(auth) => Arc::new(client.with_auth(auth.auth())),
+                    None => Arc::new(client),
+                }
+            }
             ConnectorConfig::InfluxDbClient { url, token } => Arc::new(InfluxDbClient::new(url, token)),
         }
     }
 }
+
+/// How an `http_client` authenticates its requests. Secrets are best
+/// given through `${ENV_VAR}` references.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HttpAuthConfig {
+    Basic { username: String, password: Option<String> },
+    Bearer { token: String },
+    /// OAuth2 client credentials grant; tokens are fetched from `token_url`
+    /// and renewed when they expire.
+    #[serde(rename = "oauth2")]
+    OAuth2 {
+        token_url: String,
+        client_id: String,
+        client_secret: String,
+        #[serde(default)]
+        scopes: Vec<String>,
+    },
+}
+
+impl HttpAuthConfig {
+    pub fn auth(&self) -> HttpAuth {
+        match self {
+            HttpAuthConfig::Basic { username, password } => HttpAuth::Basic { username: username.clone(), password: password.clone() },
+            HttpAuthConfig::Bearer { token } => HttpAuth::Bearer(token.clone()),
+            HttpAuthConfig::OAuth2 { token_url, client_id, client_secret, scopes } => HttpAuth::OAuth2(OAuth2ClientCredentials {
+                token_url: token_url.clone(),
+                client_id: client_id.clone(),
+                client_secret: client_secret.clone(),
+                scopes: scopes.clone(),
+            }),
+        }
+    }
+}
+
+/// Leaves the secrets out, as configs end up in debug logs.
+impl fmt::Debug for HttpAuthConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HttpAuthConfig::Basic { username, password } => f.debug_struct("Basic")
+                .field("username", username)
+                .field("password", &password.as_ref().map(|_| "<redacted>"))
+                .finish(),
+            HttpAuthConfig::Bearer { .. } => f.debug_struct("Bearer").field("token", &"<redacted>").finish(),
+            HttpAuthConfig::OAuth2 { token_url, client_id, scopes, .. } => f.debug_struct("OAuth2")
+                .field("token_url", token_url)
+                .field("client_id", client_id)
+                .field("client_secret", &"<redacted>")
+                .field("scopes", scopes)
+                .finish(),
+        }
+    }
+}
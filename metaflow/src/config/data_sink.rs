@@ -1,19 +1,164 @@
-use data_sink::data_sink::DataSink;
+use std::path::Path;
+use data_sink::data_sink::{DataSink, DataSinkOptions, WriteBatchOptions};
+use data_sink::dead_letter::{self, DeadLetter, ReinjectSummary};
+use data_sink::retry::RetryPolicy;
+use connector::{ErrorKind, ZstdDictionaries};
 use serde::{Deserialize, Serialize};
+use crate::config::auth::AuthConfig;
 use crate::config::connector::ConnectorConfig;
+use crate::config::data_source::{parse_duration, parse_optional_duration};
+use crate::config::tls::ServerTlsConfig;
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct DataSinkConfig {
     pub name: String,
     pub connector: ConnectorConfig,
+    /// `host:port`, or `unix:///path/to.sock` for a Unix domain socket.
     pub address: String,
+    pub max_frame_size: Option<usize>,
+    /// Paths of zstd dictionaries senders may compress against.
+    pub zstd_dictionaries: Option<Vec<String>>,
+    pub tls: Option<ServerTlsConfig>,
+    /// Require sources to authenticate before sending.
+    pub auth: Option<AuthConfig>,
+    /// Close source connections that have been silent for this long, such
+    /// as `60s`. Off unless set.
+    pub idle_timeout: Option<String>,
+    /// How long to wait for pending writes when shutting down, such as
+    /// `30s`.
+    pub shutdown_timeout: Option<String>,
+    /// Most writes to the connector at once.
+    pub max_in_flight: Option<usize>,
+    /// Messages that may wait for a write before sources are held back.
+    pub queue_capacity: Option<usize>,
+    /// Hand messages to the connector in batches.
+    pub batch: Option<WriteBatchConfig>,
+    /// Retry failed writes.
+    pub retry: Option<RetryConfig>,
+    /// Keep the messages that still failed after all retries.
+    pub dead_letter: Option<DeadLetterConfig>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct WriteBatchConfig {
+    pub max_messages: Option<usize>,
+    /// How long to wait for a batch to fill up, such as `50ms`.
+    pub linger: Option<String>,
+}
+
+impl WriteBatchConfig {
+    fn options(&self) -> Result<WriteBatchOptions, String> {
+        let mut options = WriteBatchOptions::default();
+        if let Some(max_messages) = self.max_messages {
+            options.max_messages = max_messages.max(1);
+        }
+        if let Some(linger) = &self.linger {
+            options.linger = parse_duration(linger)?;
+        }
+        Ok(options)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct RetryConfig {
+    pub max_attempts: Option<u32>,
+    /// Durations such as `100ms` or `10s`.
+    pub initial_backoff: Option<String>,
+    pub max_backoff: Option<String>,
+    pub multiplier: Option<f64>,
+    /// Errors worth retrying, among `timeout`, `request`, `http`,
+    /// `serialization` and `other`.
+    pub retry_on: Option<Vec<ErrorKind>>,
+}
+
+impl RetryConfig {
+    fn policy(&self) -> Result<RetryPolicy, String> {
+        let mut policy = RetryPolicy::default();
+        if let Some(max_attempts) = self.max_attempts {
+            policy.max_attempts = max_attempts.max(1);
+        }
+        if let Some(initial_backoff) = &self.initial_backoff {
+            policy.initial_backoff = parse_duration(initial_backoff)?;
+        }
+        if let Some(max_backoff) = &self.max_backoff {
+            policy.max_backoff = parse_duration(max_backoff)?;
+        }
+        if let Some(multiplier) = self.multiplier {
+            policy.multiplier = multiplier;
+        }
+        if let Some(retry_on) = &self.retry_on {
+            policy.retry_on = retry_on.iter().copied().collect();
+        }
+        Ok(policy)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DeadLetterConfig {
+    /// JSON Lines file, which `metaflow reinject` can replay.
+    File { path: String },
+    Connector { connector: ConnectorConfig },
+}
+
+impl DeadLetterConfig {
+    fn dead_letter(&self) -> DeadLetter {
+        match self {
+            DeadLetterConfig::File { path } => DeadLetter::File(path.into()),
+            DeadLetterConfig::Connector { connector } => DeadLetter::Connector(connector.create_connector()),
+        }
+    }
 }
 
 impl DataSinkConfig {
-    pub async fn start(&self) -> Result<(), Box<dyn std::error::Error>> {
+    /// Writes the messages of a dead-letter file through this sink's
+    /// connector.
+    pub async fn reinject(&self, path: &Path) -> Result<ReinjectSummary, Box<dyn std::error::Error>> {
+        Ok(dead_letter::reinject(path, self.connector.create_connector()).await?)
+    }
+
+    pub async fn start(&self) -> Result<DataSink, Box<dyn std::error::Error>> {
         let connector = self.connector.create_connector();
-        let mut data_sink = DataSink::new(connector.clone(), &self.address);
+        let mut data_sink = DataSink::with_options(connector.clone(), &self.address, self.options()?);
         data_sink.start().await?;
-        Ok(())
+        Ok(data_sink)
+    }
+
+    fn options(&self) -> Result<DataSinkOptions, Box<dyn std::error::Error>> {
+        let mut options = DataSinkOptions::default();
+        if let Some(max_frame_size) = self.max_frame_size {
+            options.server.max_frame_size = max_frame_size;
+        }
+        if let Some(paths) = &self.zstd_dictionaries {
+            options.server.dictionaries = ZstdDictionaries::load(paths)?;
+        }
+        if let Some(tls) = &self.tls {
+            options.server.tls = Some(tls.load()?);
+        }
+        if let Some(auth) = &self.auth {
+            options.server.authentication = Some(auth.authentication());
+        }
+        if let Some(idle_timeout) = &self.idle_timeout {
+            options.server.idle_timeout = parse_optional_duration(idle_timeout)?;
+        }
+        if let Some(shutdown_timeout) = &self.shutdown_timeout {
+            options.shutdown_timeout = parse_duration(shutdown_timeout)?;
+        }
+        if let Some(max_in_flight) = self.max_in_flight {
+            options.max_in_flight = max_in_flight;
+        }
+        if let Some(queue_capacity) = self.queue_capacity {
+            options.queue_capacity = queue_capacity;
+        }
+        if let Some(batch) = &self.batch {
+            options.batch = Some(batch.options()?);
+        }
+        if let Some(retry) = &self.retry {
+            options.retry = Some(retry.policy()?);
+        }
+        if let Some(dead_letter) = &self.dead_letter {
+            options.dead_letter = Some(dead_letter.dead_letter());
+        }
+        Ok(options)
     }
 }
@@ -2,10 +2,15 @@ use std::sync::Arc;
 use std::collections::HashMap;
 use std::str::FromStr;
 use serde::{Deserialize, Serialize};
-use log::{info, error};
-use connector::{Compression, Message, Protocol};
+use log::error;
+use connector::{Compression, CompressionPolicy, Message, Protocol, ZstdDictionaries};
+use connector::http::{Pagination, ResponseFormat};
+use connector::tcp::client::{BatchOptions, ClientOptions};
+use connector::tcp::{ReconnectPolicy, SpoolOptions};
 use data_source::data_source::DataSource;
+use crate::config::auth::AuthConfig;
 use crate::config::connector::ConnectorConfig;
+use crate::config::tls::ClientTlsConfig;
 use crate::config::transformation::TransformationConfig;
 use crate::config::transformation;
 
@@ -14,19 +19,138 @@ use crate::config::transformation;
 pub struct DataSourceConfig {
     pub name: String,
     pub connector: ConnectorConfig,
-    pub data_sinks: Vec<String>,
+    pub data_sinks: Vec<SinkEndpointConfig>,
     pub query: HttpRequestConfig,
     pub transformation: TransformationConfig,
+    pub compression_policy: Option<CompressionPolicy>,
+    /// Paths of zstd dictionaries available to `ZstdDict` compression.
+    pub zstd_dictionaries: Option<Vec<String>>,
+    /// Groups messages sent to the sinks into batch frames.
+    pub batch: Option<BatchConfig>,
+    /// How long to wait for a sink to acknowledge a message before sending
+    /// it again, such as `30s`.
+    pub ack_timeout: Option<String>,
+    /// Keeps messages for unreachable sinks on disk.
+    pub spool: Option<SpoolConfig>,
+    /// How to back off while a sink is unreachable.
+    pub reconnect: Option<ReconnectConfig>,
+    /// How often to ping the sinks, such as `15s`, or `off`.
+    pub heartbeat_interval: Option<String>,
+    /// Reconnect to a sink nothing has been heard from for this long, or
+    /// `off`.
+    pub idle_timeout: Option<String>,
+    /// How long to wait for the sinks to receive what was read when shutting
+    /// down, such as `30s`.
+    pub shutdown_timeout: Option<String>,
+}
+
+/// A sink to send to: either just its address, or its address with
+/// connection settings of its own.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(untagged)]
+pub enum SinkEndpointConfig {
+    Address(String),
+    Detailed {
+        address: String,
+        tls: Option<ClientTlsConfig>,
+        /// Credentials for a sink requiring authentication.
+        auth: Option<AuthConfig>,
+    },
+}
+
+impl SinkEndpointConfig {
+    pub fn address(&self) -> &str {
+        match self {
+            SinkEndpointConfig::Address(address) => address,
+            SinkEndpointConfig::Detailed { address, .. } => address,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct BatchConfig {
+    pub max_messages: Option<usize>,
+    pub max_bytes: Option<usize>,
+    /// Duration such as `50ms` or `1s`.
+    pub linger: Option<String>,
+}
+
+impl BatchConfig {
+    fn options(&self) -> Result<BatchOptions, String> {
+        let mut options = BatchOptions::default();
+        if let Some(max_messages) = self.max_messages {
+            options.max_messages = max_messages;
+        }
+        if let Some(max_bytes) = self.max_bytes {
+            options.max_bytes = max_bytes;
+        }
+        if let Some(linger) = &self.linger {
+            options.linger = parse_duration(linger)?;
+        }
+        Ok(options)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SpoolConfig {
+    /// Directory holding one spool file per sink.
+    pub dir: String,
+    pub max_bytes: Option<u64>,
+    /// Duration such as `12h`; older messages are dropped.
+    pub max_age: Option<String>,
+}
+
+impl SpoolConfig {
+    fn options(&self) -> Result<SpoolOptions, String> {
+        let mut options = SpoolOptions::new(&self.dir);
+        if let Some(max_bytes) = self.max_bytes {
+            options.max_bytes = max_bytes;
+        }
+        if let Some(max_age) = &self.max_age {
+            options.max_age = Some(parse_duration(max_age)?);
+        }
+        Ok(options)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ReconnectConfig {
+    /// Durations such as `500ms` or `1m`.
+    pub initial_delay: Option<String>,
+    pub max_delay: Option<String>,
+    pub multiplier: Option<f64>,
+    /// Fraction of each delay randomised, between 0 and 1.
+    pub jitter: Option<f64>,
+    pub max_attempts: Option<u32>,
+}
+
+impl ReconnectConfig {
+    fn policy(&self) -> Result<ReconnectPolicy, String> {
+        let mut policy = ReconnectPolicy::default();
+        if let Some(initial_delay) = &self.initial_delay {
+            policy.initial_delay = parse_duration(initial_delay)?;
+        }
+        if let Some(max_delay) = &self.max_delay {
+            policy.max_delay = parse_duration(max_delay)?;
+        }
+        if let Some(multiplier) = self.multiplier {
+            policy.multiplier = multiplier;
+        }
+        if let Some(jitter) = self.jitter {
+            policy.jitter = jitter;
+        }
+        policy.max_attempts = self.max_attempts;
+        Ok(policy)
+    }
 }
 
 impl DataSourceConfig {
-    pub async fn start(&self) -> Result<(), Box<dyn std::error::Error>> {
+    /// Builds the data source, ready to be started.
+    pub fn data_source(&self) -> Result<DataSource, Box<dyn std::error::Error>> {
         let connector = self.connector.create_connector();
 
         let transformation_config = self.transformation.clone();
 
-        info!("Starting data source...");
-
         let transformation_fn: Option<Arc<dyn Fn(Message) -> Message + Send + Sync>> = match transformation::get(&self.transformation.name) {
             Some(transformation_fn) => Some(Arc::new(
                 move |msg| {
@@ -39,21 +163,62 @@ impl DataSourceConfig {
             }
         };
 
-        let data_source = DataSource::new(
+        let client_options = self.client_options()?;
+        let mut data_source = DataSource::new(
             connector.clone(),
-            Message {
-                compression: Compression::None,
-                payload: Protocol::Json(serde_json::to_value(&self.query).unwrap()),
-            },
+            Message::new(Compression::None, Protocol::Json(serde_json::to_value(&self.query).unwrap())),
             Some(self.query.timeout_duration),
-            Some(self.data_sinks.clone()),
+            Some(self.data_sinks.iter().map(|sink| sink.address().to_string()).collect()),
             None,
             transformation_fn,
-        );
+        )
+        .with_source_name(&self.name)
+        .with_client_options(client_options.clone());
+        if let Some(shutdown_timeout) = &self.shutdown_timeout {
+            data_source = data_source.with_shutdown_timeout(parse_duration(shutdown_timeout)?);
+        }
 
-        data_source.start().await?;
+        for sink in &self.data_sinks {
+            if let SinkEndpointConfig::Detailed { address, tls, auth } = sink {
+                let options = ClientOptions {
+                    tls: tls.as_ref().map(ClientTlsConfig::load).transpose()?,
+                    authentication: auth.as_ref().map(AuthConfig::authentication),
+                    ..client_options.clone()
+                };
+                data_source = data_source.with_sink_options(address, options);
+            }
+        }
+
+        Ok(data_source)
+    }
 
-        Ok(())
+    fn client_options(&self) -> Result<ClientOptions, Box<dyn std::error::Error>> {
+        let mut options = ClientOptions::default();
+        if let Some(compression_policy) = &self.compression_policy {
+            options.compression_policy = compression_policy.clone();
+        }
+        if let Some(paths) = &self.zstd_dictionaries {
+            options.dictionaries = ZstdDictionaries::load(paths)?;
+        }
+        if let Some(batch) = &self.batch {
+            options.batch = Some(batch.options()?);
+        }
+        if let Some(ack_timeout) = &self.ack_timeout {
+            options.ack_timeout = Some(parse_duration(ack_timeout)?);
+        }
+        if let Some(spool) = &self.spool {
+            options.spool = Some(spool.options()?);
+        }
+        if let Some(reconnect) = &self.reconnect {
+            options.reconnect = reconnect.policy()?;
+        }
+        if let Some(heartbeat_interval) = &self.heartbeat_interval {
+            options.heartbeat_interval = parse_optional_duration(heartbeat_interval)?;
+        }
+        if let Some(idle_timeout) = &self.idle_timeout {
+            options.idle_timeout = parse_optional_duration(idle_timeout)?;
+        }
+        Ok(options)
     }
 }
 
@@ -63,9 +228,36 @@ pub struct HttpRequestConfig {
     pub method: String,
     pub headers: HashMap<String, String>,
     pub query_params: HashMap<String, String>,
-    pub body: Option<HashMap<String, serde_json::Value>>,
+    /// Encoded according to the `Content-Type` header: an object for JSON
+    /// or forms, an array of rows for CSV, a string for text, and so on.
+    pub body: Option<serde_json::Value>,
     #[serde(deserialize_with = "deserialize_duration")]
     pub timeout_duration: std::time::Duration,
+    /// Retry failed polls instead of waiting for the next interval.
+    pub retry: Option<HttpRetryConfig>,
+    /// Follow the pages of a paged API.
+    pub pagination: Option<Pagination>,
+    /// Read responses as `json`, `csv`, `xml`, `text`, `prometheus` or
+    /// `raw` instead of going by their `Content-Type`.
+    pub response_format: Option<ResponseFormat>,
+}
+
+/// Serializes to the `HttpRetryPolicy` of the request, which fills in the
+/// settings left out.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct HttpRetryConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_attempts: Option<u32>,
+    /// Durations such as `500ms` or `30s`.
+    #[serde(default, deserialize_with = "deserialize_optional_duration", skip_serializing_if = "Option::is_none")]
+    pub initial_backoff: Option<std::time::Duration>,
+    #[serde(default, deserialize_with = "deserialize_optional_duration", skip_serializing_if = "Option::is_none")]
+    pub max_backoff: Option<std::time::Duration>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub multiplier: Option<f64>,
+    /// Give up once retrying would take longer than this in all.
+    #[serde(default, deserialize_with = "deserialize_optional_duration", skip_serializing_if = "Option::is_none")]
+    pub max_elapsed: Option<std::time::Duration>,
 }
 
 fn deserialize_duration<'de, D>(deserializer: D) -> Result<std::time::Duration, D::Error>
@@ -76,7 +268,26 @@ where
     parse_duration(&s).map_err(serde::de::Error::custom)
 }
 
-fn parse_duration(s: &str) -> Result<std::time::Duration, String> {
+fn deserialize_optional_duration<'de, D>(deserializer: D) -> Result<Option<std::time::Duration>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    deserialize_duration(deserializer).map(Some)
+}
+
+/// Like `parse_duration`, with `off` or `none` disabling the setting.
+pub(crate) fn parse_optional_duration(s: &str) -> Result<Option<std::time::Duration>, String> {
+    match s {
+        "off" | "none" => Ok(None),
+        _ => parse_duration(s).map(Some),
+    }
+}
+
+pub(crate) fn parse_duration(s: &str) -> Result<std::time::Duration, String> {
+    if let Some(value) = s.strip_suffix("ms") {
+        let number = u64::from_str(value).map_err(|_| "Invalid number")?;
+        return Ok(std::time::Duration::from_millis(number));
+    }
     let unit = &s[s.len() - 1..];
     let value = &s[..s.len() - 1];
     let number = u64::from_str(value).map_err(|_| "Invalid number")?;
@@ -1,4 +1,6 @@
+pub mod auth;
 pub mod connector;
 pub mod data_sink;
 pub mod data_source;
-pub mod transformation;
\ No newline at end of file
+pub mod tls;
+pub mod transformation;
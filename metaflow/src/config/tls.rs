@@ -0,0 +1,44 @@
+use serde::{Deserialize, Serialize};
+use connector::tcp::{ClientTls, ServerTls};
+
+/// PEM files for a sink accepting TLS connections.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ServerTlsConfig {
+    pub cert: String,
+    pub key: String,
+    /// CA bundle client certificates must chain to; enables mutual TLS.
+    pub client_ca: Option<String>,
+}
+
+impl ServerTlsConfig {
+    pub fn load(&self) -> Result<ServerTls, std::io::Error> {
+        ServerTls::load(&self.cert, &self.key, self.client_ca.as_ref())
+    }
+}
+
+/// PEM files for a source connecting to a sink over TLS.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ClientTlsConfig {
+    /// CA bundle the sink certificate must chain to; the Mozilla root store
+    /// when omitted.
+    pub ca: Option<String>,
+    /// Client certificate and key, for sinks requiring mutual TLS.
+    pub cert: Option<String>,
+    pub key: Option<String>,
+    pub server_name: Option<String>,
+}
+
+impl ClientTlsConfig {
+    pub fn load(&self) -> Result<ClientTls, std::io::Error> {
+        let identity = match (&self.cert, &self.key) {
+            (Some(cert), Some(key)) => Some((cert, key)),
+            (None, None) => None,
+            _ => return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "TLS cert and key must be set together")),
+        };
+        let tls = ClientTls::load(self.ca.as_ref(), identity)?;
+        Ok(match &self.server_name {
+            Some(server_name) => tls.with_server_name(server_name),
+            None => tls,
+        })
+    }
+}
@@ -3,7 +3,7 @@ use std::sync::Mutex;
 use std::time::SystemTime;
 use serde::{Deserialize, Serialize};
 use log::error;
-use connector::{Message, Compression, Protocol};
+use connector::{Message, Compression, Envelope, Protocol};
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct TransformationConfig {
@@ -24,6 +24,9 @@ pub enum TransformationSourceConfig {
     Literal(String),
     Field(String),
     Computed(String),
+    /// Envelope metadata: `id`, `created_at`, `source`, `redeliveries` or
+    /// `headers.<name>`.
+    Envelope(String),
     Object(HashMap<String, TransformationSourceConfig>),
 }
 
@@ -43,41 +46,37 @@ pub fn get(name: &str) -> Option<fn(&TransformationConfig, Message) -> Message>
 }
 
 #[allow(dead_code)]
-pub fn get_value(source: &TransformationSourceConfig, protocol: &Protocol) -> Protocol {
+pub fn get_value(source: &TransformationSourceConfig, protocol: &Protocol, envelope: &Envelope) -> Protocol {
     match source {
-        TransformationSourceConfig::Literal(value) => match protocol {
-            Protocol::Json(_) => Protocol::Json(serde_json::Value::String(value.clone())),
+        TransformationSourceConfig::Literal(value) => protocol.with_value(serde_json::Value::String(value.clone())),
+        TransformationSourceConfig::Field(path) => extract_value_from_json(protocol.value(), path)
+            .map(|value| protocol.with_value(value))
+            .unwrap_or_else(|| {
+                error!("Field {} not found in response", path);
+                protocol.with_value(serde_json::Value::String("".to_string()))
+            }),
+        TransformationSourceConfig::Computed(value) => {
+            if value == "current_timestamp" {
+                protocol.with_value(serde_json::Value::Number(serde_json::Number::from(
+                    SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs(),
+                )))
+            } else {
+                error!("Computed value {} is not supported", value);
+                protocol.with_value(serde_json::Value::String("".to_string()))
+            }
         },
-        TransformationSourceConfig::Field(path) => match protocol {
-            Protocol::Json(json) => extract_value_from_json(json, path)
-                .map(Protocol::Json)
-                .unwrap_or_else(|| {
-                    error!("Field {} not found in response", path);
-                    Protocol::Json(serde_json::Value::String("".to_string()))
-                }),
-        },
-        TransformationSourceConfig::Computed(value) => match protocol {
-            Protocol::Json(_) => {
-                if value == "current_timestamp" {
-                    Protocol::Json(serde_json::Value::Number(serde_json::Number::from(
-                        SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs(),
-                    )))
-                } else {
-                    error!("Computed value {} is not supported", value);
-                    Protocol::Json(serde_json::Value::String("".to_string()))
-                }
-            },
-        },
-        TransformationSourceConfig::Object(fields) => match protocol {
-            Protocol::Json(_) => {
-                let mut field_data = serde_json::Map::new();
-                for (field_key, field) in fields {
-                    let value = get_value(field, protocol);
-                    let Protocol::Json(json_value) = value;
-                    field_data.insert(field_key.clone(), json_value);
-                }
-                Protocol::Json(serde_json::Value::Object(field_data))
-            },
+        TransformationSourceConfig::Envelope(path) => extract_value_from_envelope(envelope, path)
+            .map(|value| protocol.with_value(value))
+            .unwrap_or_else(|| {
+                error!("Envelope field {} not found", path);
+                protocol.with_value(serde_json::Value::String("".to_string()))
+            }),
+        TransformationSourceConfig::Object(fields) => {
+            let mut field_data = serde_json::Map::new();
+            for (field_key, field) in fields {
+                field_data.insert(field_key.clone(), get_value(field, protocol, envelope).into_value());
+            }
+            protocol.with_value(serde_json::Value::Object(field_data))
         },
     }
 }
@@ -87,10 +86,21 @@ fn extract_value_from_json(json: &serde_json::Value, path: &str) -> Option<serde
     let keys: Vec<&str> = path.split('.').collect();
     let mut current = json;
     for key in keys {
-        current = match current.get(key) {
-            Some(value) => value,
-            None => return None,
-        };
+        current = current.get(key)?;
     }
     Some(current.clone())
 }
+
+fn extract_value_from_envelope(envelope: &Envelope, path: &str) -> Option<serde_json::Value> {
+    match path {
+        "id" => Some(serde_json::Value::String(envelope.id.to_string())),
+        "created_at" => Some(serde_json::Value::Number(serde_json::Number::from(
+            envelope.created_at.duration_since(SystemTime::UNIX_EPOCH).ok()?.as_secs(),
+        ))),
+        "source" => envelope.source.clone().map(serde_json::Value::String),
+        "redeliveries" => Some(serde_json::Value::Number(envelope.redeliveries.into())),
+        _ => path.strip_prefix("headers.")
+            .and_then(|name| envelope.headers.get(name))
+            .map(|value| serde_json::Value::String(value.clone())),
+    }
+}
@@ -2,9 +2,12 @@ use crate::config::data_source::DataSourceConfig;
 use crate::config::data_sink::DataSinkConfig;
 use std::fs;
 use std::env;
+use std::future::Future;
+use std::path::Path;
 use regex::Regex;
 
-use log::info;
+use data_sink::dead_letter::ReinjectSummary;
+use log::{info, warn};
 use serde::Deserialize;
 
 mod config;
@@ -37,24 +40,68 @@ impl MetaFlow {
         Ok(result.into_owned())
     }
     
+    /// Writes the messages dead-lettered to `path` through the connector of
+    /// the data sink called `sink_name`.
+    pub async fn reinject(&self, sink_name: &str, path: &Path) -> Result<ReinjectSummary, Box<dyn std::error::Error>> {
+        let data_sink = self.data_sinks.iter().flatten()
+            .find(|data_sink| data_sink.name == sink_name)
+            .ok_or_else(|| format!("No data sink named {}", sink_name))?;
+        data_sink.reinject(path).await
+    }
+
     pub async fn start(&self) -> Result<(), Box<dyn std::error::Error>> {
+        self.run(std::future::pending()).await
+    }
 
+    /// Runs until `shutdown` completes, then drains the data sources so the
+    /// messages they already read reach the sinks, and the data sinks so the
+    /// messages they already received are written.
+    pub async fn run(&self, shutdown: impl Future<Output = ()>) -> Result<(), Box<dyn std::error::Error>> {
+        let mut sinks = Vec::new();
         if let Some(data_sinks) = &self.data_sinks {
             if !data_sinks.is_empty() {
                 info!("Starting data sinks...");
                 for data_sink_config in data_sinks {
-                    data_sink_config.start().await?;
+                    sinks.push((data_sink_config.name.clone(), data_sink_config.start().await?));
                 }
             }
         }
 
-        if let Some(data_sources) = &self.data_sources {
-            if !data_sources.is_empty() {
+        let mut sources = Vec::new();
+        for data_source_config in self.data_sources.iter().flatten() {
+            sources.push((data_source_config.name.clone(), data_source_config.data_source()?));
+        }
+        let running = async {
+            if !sources.is_empty() {
                 info!("Starting data sources...");
-                for data_source_config in data_sources {
-                    data_source_config.start().await?;
+                for (_, data_source) in &sources {
+                    data_source.start().await?;
                 }
             }
+            Ok::<(), Box<dyn std::error::Error>>(())
+        };
+
+        tokio::pin!(shutdown);
+        tokio::select! {
+            result = running => {
+                result?;
+                // The sinks keep serving until told to stop
+                (&mut shutdown).await;
+            }
+            _ = &mut shutdown => {}
+        }
+
+        info!("Shutting down...");
+        for (name, data_source) in &sources {
+            if data_source.shutdown().await {
+                info!("Data source {} shut down", name);
+            } else {
+                warn!("Data source {} shut down with messages still undelivered", name);
+            }
+        }
+        for (name, mut data_sink) in sinks {
+            let summary = data_sink.shutdown().await;
+            info!("Data sink {} shut down: {}", name, summary);
         }
 
         Ok(())